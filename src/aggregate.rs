@@ -0,0 +1,205 @@
+use color_thief::Color;
+use std::collections::HashMap;
+
+use crate::image_file_lib::sort_by_contrast;
+use crate::images_batch::ImagesColors;
+use crate::{ExtractionOptions, Palette};
+
+/// Weight of each image in an aggregate palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImagesWeights {
+    /// Proportional to their sampled pixels
+    Pixels,
+    /// The same for all the images, whatever their resolution
+    Equal,
+}
+
+/// Palette of a whole collection, quantized from the histogram of the pooled colors
+/// of the images palettes, weighted by their proportions and the images weights
+pub fn aggregate_palette(
+    images_colors_map: &ImagesColors,
+    nb_colors: u8,
+    options: &ExtractionOptions,
+    images_weights: ImagesWeights,
+) -> Result<Palette, String> {
+    let palettes = images_colors_map
+        .values()
+        .filter_map(|colors_result| colors_result.as_ref().ok())
+        .collect::<Vec<&Palette>>();
+    let mut weighted_colors = vec![];
+    let mut background = vec![];
+    for palette in &palettes {
+        let image_weight = match images_weights {
+            ImagesWeights::Pixels => palette.pixels as f32,
+            ImagesWeights::Equal => 1.0,
+        };
+        for (index, color) in palette.colors.iter().enumerate() {
+            let proportion = palette
+                .proportions
+                .get(index)
+                .copied()
+                .unwrap_or(1.0 / palette.colors.len() as f32);
+            weighted_colors.push((*color, proportion * image_weight));
+        }
+        for color in &palette.background {
+            if !background.contains(color) {
+                background.push(*color);
+            }
+        }
+    }
+    let total_weight = weighted_colors
+        .iter()
+        .map(|(_, weight)| weight)
+        .sum::<f32>();
+    if total_weight <= 0.0 {
+        return Err("No images colors to aggregate.".to_string());
+    }
+    let quantized = quantize_histogram(weighted_colors, nb_colors);
+    let mut colors = quantized
+        .iter()
+        .map(|(color, _)| *color)
+        .collect::<Vec<Color>>();
+    sort_by_contrast(&mut colors, options);
+    let proportions = colors
+        .iter()
+        .map(|color| {
+            let (_, weight) = quantized.iter().find(|(c, _)| c == color).unwrap();
+            weight / total_weight
+        })
+        .collect();
+    Ok(Palette {
+        colors,
+        background,
+        proportions,
+        pixels: palettes.iter().map(|palette| palette.pixels).sum(),
+    })
+}
+
+/// Distinct colors of a weighted colors histogram, with their weights, the heaviest first
+/// The histogram is split by weighted median cuts along the widest channel,
+/// the heaviest and widest box first, and each box gives its mean color.
+fn quantize_histogram(weighted_colors: Vec<(Color, f32)>, nb_colors: u8) -> Vec<(Color, f32)> {
+    let mut histogram = HashMap::<[u8; 3], f32>::new();
+    for (color, weight) in weighted_colors {
+        *histogram.entry([color.r, color.g, color.b]).or_default() += weight;
+    }
+    let mut boxes = vec![ColorBox {
+        colors: histogram
+            .into_iter()
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect(),
+    }];
+    while boxes.len() < nb_colors as usize {
+        let split_box = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, color_box)| (index, color_box.weight() * color_box.widest_channel().1))
+            .filter(|(_, priority)| *priority > 0.0)
+            .max_by(|(_, priority1), (_, priority2)| priority1.total_cmp(priority2));
+        let Some((index, _)) = split_box else {
+            break;
+        };
+        let (box1, box2) = boxes.swap_remove(index).split();
+        boxes.push(box1);
+        boxes.push(box2);
+    }
+    let mut quantized = Vec::<(Color, f32)>::with_capacity(boxes.len());
+    for color_box in boxes {
+        let (color, weight) = (color_box.mean_color(), color_box.weight());
+        match quantized.iter_mut().find(|(c, _)| *c == color) {
+            Some((_, total_weight)) => *total_weight += weight,
+            None => quantized.push((color, weight)),
+        }
+    }
+    quantized.sort_by(|(_, weight1), (_, weight2)| weight2.total_cmp(weight1));
+    quantized
+}
+
+/// Colors of a histogram box, with their weights
+struct ColorBox {
+    colors: Vec<([u8; 3], f32)>,
+}
+
+impl ColorBox {
+    fn weight(&self) -> f32 {
+        self.colors.iter().map(|(_, weight)| weight).sum()
+    }
+
+    /// Channel of the largest values range, with its range
+    fn widest_channel(&self) -> (usize, f32) {
+        (0..3)
+            .map(|channel| {
+                let values = self.colors.iter().map(|(color, _)| color[channel]);
+                let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+                (channel, range as f32)
+            })
+            .max_by(|(_, range1), (_, range2)| range1.total_cmp(range2))
+            .unwrap()
+    }
+
+    fn mean_color(&self) -> Color {
+        let weight = self.weight();
+        let mean = |channel: usize| {
+            let sum = self
+                .colors
+                .iter()
+                .map(|(color, weight)| color[channel] as f32 * weight)
+                .sum::<f32>();
+            (sum / weight).round().clamp(0.0, 255.0) as u8
+        };
+        Color {
+            r: mean(0),
+            g: mean(1),
+            b: mean(2),
+        }
+    }
+
+    /// Split at the weighted median of the widest channel, both boxes keeping a color
+    fn split(mut self) -> (ColorBox, ColorBox) {
+        let (channel, _) = self.widest_channel();
+        self.colors.sort_by_key(|(color, _)| color[channel]);
+        let half_weight = self.weight() / 2.0;
+        let mut cumulated_weight = 0.0;
+        let median_index = self
+            .colors
+            .iter()
+            .position(|(_, weight)| {
+                cumulated_weight += weight;
+                cumulated_weight >= half_weight
+            })
+            .unwrap_or(0);
+        let split_index = (median_index + 1).clamp(1, self.colors.len() - 1);
+        let colors2 = self.colors.split_off(split_index);
+        (self, ColorBox { colors: colors2 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color {
+        r: 220,
+        g: 20,
+        b: 20,
+    };
+    const BLUE: Color = Color {
+        r: 20,
+        g: 20,
+        b: 220,
+    };
+
+    #[test]
+    fn weighted_histogram() {
+        let weighted_colors = vec![(RED, 3.0), (BLUE, 1.0), (RED, 1.0)];
+        assert_eq!(
+            quantize_histogram(weighted_colors.clone(), 2),
+            [(RED, 4.0), (BLUE, 1.0)]
+        );
+        // A single box gives the weighted mean color
+        let [(mean_color, weight)] = quantize_histogram(weighted_colors, 1)[..] else {
+            panic!("a single color is expected");
+        };
+        assert_eq!((mean_color.r, mean_color.b, weight), (180, 60, 5.0));
+    }
+}
@@ -1,9 +1,8 @@
-use color_thief::Color;
 use copypasta::{ClipboardContext, ClipboardProvider};
 use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
 use ratatui::{prelude::style::Color as RatatuiColor, prelude::*, widgets::*, DefaultTerminal};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashSet},
     error::Error,
     io, process,
     sync::{Arc, Mutex},
@@ -11,8 +10,15 @@ use std::{
 };
 use Constraint::{Length, Ratio};
 
+use crate::aggregate::{aggregate_palette, ImagesWeights};
+use crate::cluster::{cluster_images, ClusterCriterion, ImagesGroup};
+use crate::images_batch::{get_extracted_colors, update_images_colors};
+use crate::near::NearQuery;
+use crate::uri::path_label;
+use crate::watch::ImagesWatcher;
 use crate::ColorsCanvas;
-use crate::ImageFile;
+use crate::ExtractionOptions;
+use crate::Palette;
 
 const TERMINAL_GREEN: RatatuiColor = RatatuiColor::Rgb(124, 252, 0);
 
@@ -201,6 +207,9 @@ impl<T> StatefulList<T> {
     }
 
     fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -219,6 +228,9 @@ impl<T> StatefulList<T> {
     }
 
     fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -242,53 +254,217 @@ impl<T> StatefulList<T> {
     }
 }
 
+/// Optional features of the images browsing
+#[derive(Default)]
+pub struct BrowseOptions {
+    /// Watcher updating the images live
+    pub watcher: Option<ImagesWatcher>,
+    /// Aggregate palette shown above the images
+    pub aggregate_weights: Option<ImagesWeights>,
+    /// Ranking of the images closest to some colors
+    pub near: Option<NearQuery>,
+    /// Grouping of the images with similar palettes
+    pub cluster: Option<ClusterCriterion>,
+}
+
+/// Row of the files list
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ListRow {
+    /// Group of images, by its index
+    Group(usize),
+    Image(String),
+}
+
 /// Images browsing app
 pub struct App<'a> {
-    images_paths: BTreeMap<String, Result<Vec<Color>, String>>,
+    images_paths: BTreeMap<String, Result<Palette, String>>,
+    /// Listed groups and images, in their displaying order
+    rows: Vec<ListRow>,
+    groups: Vec<ImagesGroup>,
+    collapsed_groups: HashSet<usize>,
     items: StatefulList<String>,
     input_mode: InputMode,
     nb_colors: u8,
     nb_extracted_colors: u8,
     with_rgb: bool,
-    excluded_colors: &'a Vec<Color>,
-    bc_color: Option<&'a Color>,
+    options: &'a ExtractionOptions,
+    dir_path: String,
+    browse_options: BrowseOptions,
+    /// Palette of all the images, when aggregated
+    aggregate: Option<Result<Palette, String>>,
+    /// Palette extracted with more or less colors, by image path and number of colors
+    extracted_palette: Option<((String, u8), Result<Palette, String>)>,
 }
 
 impl<'a> App<'a> {
     pub fn new(
-        images_paths: BTreeMap<String, Result<Vec<Color>, String>>,
+        images_paths: BTreeMap<String, Result<Palette, String>>,
         dir_path: &str,
         nb_colors: u8,
         with_rgb: bool,
-        excluded_colors: &'a Vec<Color>,
-        bc_color: Option<&'a Color>,
+        options: &'a ExtractionOptions,
+        browse_options: BrowseOptions,
     ) -> App<'a> {
-        let dir_path = if !dir_path.ends_with("/") {
-            format!("{dir_path}/")
-        } else {
+        // Images read from a list are not relative to a directory
+        let dir_path = if dir_path.is_empty() || dir_path.ends_with('/') {
             dir_path.to_string()
+        } else {
+            format!("{dir_path}/")
         };
-        let images_items = images_paths
-            .iter()
-            .map(|(file, _)| file.replace(&dir_path, ""))
-            .collect();
-        App {
+        let mut app = App {
             images_paths,
-            items: StatefulList::with_items(images_items),
+            rows: vec![],
+            groups: vec![],
+            collapsed_groups: HashSet::new(),
+            items: StatefulList::with_items(vec![]),
             input_mode: InputMode::Normal,
             nb_colors,
             nb_extracted_colors: nb_colors,
             with_rgb,
-            excluded_colors,
-            bc_color,
+            options,
+            dir_path,
+            browse_options,
+            aggregate: None,
+            extracted_palette: None,
+        };
+        app.update_groups();
+        app.update_items();
+        app.update_aggregate();
+        app
+    }
+
+    fn update_groups(&mut self) {
+        if let Some(criterion) = self.browse_options.cluster {
+            self.groups =
+                cluster_images(&self.images_paths, criterion, self.nb_colors, self.options);
+            self.collapsed_groups.clear();
         }
     }
 
+    fn update_aggregate(&mut self) {
+        self.aggregate = self.browse_options.aggregate_weights.map(|images_weights| {
+            aggregate_palette(
+                &self.images_paths,
+                self.nb_colors,
+                self.options,
+                images_weights,
+            )
+        });
+    }
+
+    /// List the images by path, by distance to the near colors, or by group
+    fn update_items(&mut self) {
+        if self.browse_options.cluster.is_some() {
+            self.rows.clear();
+            self.items.items.clear();
+            for (index, group) in self.groups.iter().enumerate() {
+                let is_collapsed = self.collapsed_groups.contains(&index);
+                let fold_symbol = if is_collapsed { "▸" } else { "▾" };
+                self.rows.push(ListRow::Group(index));
+                self.items.items.push(format!(
+                    "{fold_symbol} Group {} · {} images",
+                    index + 1,
+                    group.paths.len()
+                ));
+                if is_collapsed {
+                    continue;
+                }
+                for path in &group.paths {
+                    let file = path_label(path);
+                    let file = file.strip_prefix(&self.dir_path).unwrap_or(&file);
+                    self.rows.push(ListRow::Image(path.clone()));
+                    self.items.items.push(format!("    {file}"));
+                }
+            }
+            return;
+        }
+        let images_distances = match &self.browse_options.near {
+            Some(near) => near
+                .rank(&self.images_paths)
+                .into_iter()
+                .map(|(path, distance)| (path, Some(distance)))
+                .collect(),
+            None => self
+                .images_paths
+                .keys()
+                .map(|path| (path.clone(), None))
+                .collect::<Vec<(String, Option<f32>)>>(),
+        };
+        self.items.items = images_distances
+            .iter()
+            .map(|(path, distance)| {
+                let file = path_label(path);
+                let file = file.strip_prefix(&self.dir_path).unwrap_or(&file);
+                match distance {
+                    Some(distance) => format!("{file} · ΔE {distance:.1}"),
+                    None => file.to_string(),
+                }
+            })
+            .collect();
+        self.rows = images_distances
+            .into_iter()
+            .map(|(path, _)| ListRow::Image(path))
+            .collect();
+    }
+
+    /// Fold or unfold the selected group
+    fn toggle_group(&mut self) {
+        let selected_row = self
+            .items
+            .state
+            .selected()
+            .and_then(|index| self.rows.get(index));
+        if let Some(ListRow::Group(group_index)) = selected_row {
+            if !self.collapsed_groups.remove(group_index) {
+                self.collapsed_groups.insert(*group_index);
+            }
+            self.update_items();
+        }
+    }
+
+    /// Update the changed images, keeping the selected one
+    fn update_watched_images(&mut self) {
+        let Some(watcher) = &mut self.browse_options.watcher else {
+            return;
+        };
+        let changed_paths = watcher.changed_paths();
+        if changed_paths.is_empty() {
+            return;
+        }
+        update_images_colors(
+            &mut self.images_paths,
+            changed_paths,
+            watcher,
+            self.nb_colors,
+            self.options,
+        );
+        let selected_index = self.items.state.selected();
+        let selected_row = selected_index.and_then(|index| self.rows.get(index).cloned());
+        self.update_groups();
+        self.update_items();
+        self.update_aggregate();
+        let Some(selected_index) = selected_index else {
+            return;
+        };
+        if self.items.items.is_empty() {
+            self.input_mode = InputMode::Normal;
+            self.items.unselect();
+            return;
+        }
+        // A removed image leaves the selection at its position
+        let index = selected_row
+            .and_then(|selected_row| self.rows.iter().position(|row| *row == selected_row))
+            .unwrap_or(selected_index.min(self.items.items.len() - 1));
+        self.items.state.select(Some(index));
+        self.items.vertical_scroll_state = self.items.vertical_scroll_state.position(index);
+    }
+
     fn increment_nb_extracted_colors(&mut self) {
         // interactively increment number of colors extracted up to 10
         if self.nb_extracted_colors < 10 {
             self.nb_extracted_colors += 1;
         }
+        self.update_extracted_palette();
     }
 
     fn decrement_nb_extracted_colors(&mut self) {
@@ -296,6 +472,30 @@ impl<'a> App<'a> {
         if self.nb_extracted_colors > 2 {
             self.nb_extracted_colors -= 1;
         }
+        self.update_extracted_palette();
+    }
+
+    /// Extract the selected image colors again, unless already extracted
+    /// with that number of colors, as the redraws reuse them
+    fn update_extracted_palette(&mut self) {
+        let Some(ListRow::Image(path)) = self
+            .items
+            .state
+            .selected()
+            .and_then(|index| self.rows.get(index))
+        else {
+            return;
+        };
+        let key = (path.clone(), self.nb_extracted_colors);
+        if self
+            .extracted_palette
+            .as_ref()
+            .is_some_and(|(extracted_key, _)| *extracted_key == key)
+        {
+            return;
+        }
+        let palette = get_extracted_colors(path.clone(), self.nb_extracted_colors, self.options);
+        self.extracted_palette = Some((key, palette));
     }
 }
 
@@ -303,10 +503,11 @@ pub fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     mut app: App,
     tick_rate: Duration,
-    ctx: &mut ClipboardContext,
+    ctx: &mut Option<ClipboardContext>,
 ) -> io::Result<()> {
     let last_tick = Instant::now();
     loop {
+        app.update_watched_images();
         terminal.draw(|f| ui(f, &mut app, ctx))?;
 
         let timeout = tick_rate.saturating_sub(last_tick.elapsed());
@@ -317,6 +518,12 @@ pub fn run_app<B: Backend>(
                         if key.kind == KeyEventKind::Press {
                             match key.code {
                                 KeyCode::Char('q') | KeyCode::Char('Q') => return Ok(()),
+                                KeyCode::Char('c') | KeyCode::Char('C')
+                                    if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                                {
+                                    app.items.start_time = Instant::now();
+                                    app.items.clip_color = true;
+                                }
                                 KeyCode::Down => {
                                     app.input_mode = InputMode::Browsing;
                                     app.items.next();
@@ -342,13 +549,14 @@ pub fn run_app<B: Backend>(
                                 KeyCode::Up => {
                                     app.items.previous();
                                 }
-                                KeyCode::Char('c') | KeyCode::Char('C') => {
-                                    if key.modifiers.contains(KeyModifiers::CONTROL) {
-                                        app.items.start_time = Instant::now();
-                                        app.items.clip_color = true;
-                                    }
+                                KeyCode::Char('c') | KeyCode::Char('C')
+                                    if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                                {
+                                    app.items.start_time = Instant::now();
+                                    app.items.clip_color = true;
                                 }
                                 KeyCode::Char('q') | KeyCode::Char('Q') => return Ok(()),
+                                KeyCode::Enter | KeyCode::Char(' ') => app.toggle_group(),
                                 KeyCode::Char('l') | KeyCode::Char('L') => {
                                     app.items.select_less = true;
                                     app.decrement_nb_extracted_colors()
@@ -367,24 +575,73 @@ pub fn run_app<B: Backend>(
     }
 }
 
+fn render_aggregate(
+    frame: &mut Frame,
+    app: &App,
+    aggregate: Result<Palette, String>,
+    area: Rect,
+    ctx: &mut Option<ClipboardContext>,
+) {
+    let nb_images = app
+        .images_paths
+        .values()
+        .filter(|colors| colors.is_ok())
+        .count();
+    let mut title = format!("Aggregate palette · {nb_images} images");
+    let cv;
+    let aggregate_text = match aggregate {
+        Ok(palette) => {
+            cv = ColorsCanvas::new(palette.colors, false, app.with_rgb, app.items.clip_color);
+            let duration = Duration::from_secs(3); // clipboarding message duration
+            if app.input_mode == InputMode::Normal
+                && app.items.clip_color
+                && app.items.get_start_time().elapsed() <= duration
+            {
+                if copy_to_clipboard(ctx, cv.colors_clipboarded().unwrap()) {
+                    title.push_str(" - copied to clipboard!");
+                } else {
+                    title.push_str(" - no clipboard available!");
+                }
+            }
+            Line::from(cv.tui_text())
+        }
+        Err(error_message) => Line::from(error_message),
+    };
+    frame.render_widget(
+        Paragraph::new(aggregate_text)
+            .alignment(Alignment::Center)
+            .block(Block::default().title(title).borders(Borders::ALL)),
+        area,
+    );
+}
+
 fn green_terminal_text(text: &str) -> Span<'_> {
     Span::styled(
         text,
         Style::new().fg(TERMINAL_GREEN).add_modifier(Modifier::BOLD),
     )
 }
-fn ui(frame: &mut Frame, app: &mut App, ctx: &mut ClipboardContext) {
+/// Copy to the clipboard, when one is available
+fn copy_to_clipboard(ctx: &mut Option<ClipboardContext>, contents: String) -> bool {
+    ctx.as_mut()
+        .is_some_and(|ctx| ctx.set_contents(contents).is_ok())
+}
+
+fn ui(frame: &mut Frame, app: &mut App, ctx: &mut Option<ClipboardContext>) {
+    // The aggregate palette is shown above the files
+    let aggregate_height = if app.aggregate.is_some() { 3 } else { 0 };
     let main_layout = Layout::new(
         Direction::Vertical,
         [
             Constraint::Length(1),
+            Constraint::Length(aggregate_height),
             Constraint::Min(9),
             Constraint::Min(0),
         ],
     )
     .split(frame.area());
 
-    let help_message = match app.input_mode {
+    let mut help_message = match app.input_mode {
         InputMode::Normal => vec![
             "Press ".into(),
             "[".into(),
@@ -418,9 +675,35 @@ fn ui(frame: &mut Frame, app: &mut App, ctx: &mut ClipboardContext) {
         ],
     };
 
+    if app.input_mode == InputMode::Browsing && app.browse_options.cluster.is_some() {
+        help_message.splice(
+            0..0,
+            [
+                "Press ".into(),
+                "[".into(),
+                green_terminal_text("Enter"),
+                "] ".into(),
+                "to fold a group. ".into(),
+            ],
+        );
+    }
+    if app.input_mode == InputMode::Normal && app.aggregate.is_some() {
+        help_message.splice(
+            help_message.len() - 5..help_message.len() - 5,
+            [
+                "[".into(),
+                green_terminal_text("Ctrl+c"),
+                "] ".into(),
+                "to copy the aggregate palette. ".into(),
+            ],
+        );
+    }
     let help_message = Text::from(Line::from(help_message));
     let help_message = Paragraph::new(help_message).scroll((app.items.vertical_scroll as u16, 0));
     frame.render_widget(help_message, main_layout[0]);
+    if let Some(aggregate) = &app.aggregate {
+        render_aggregate(frame, app, aggregate.clone(), main_layout[1], ctx);
+    }
     let items: Vec<ListItem> = app
         .items
         .items
@@ -452,34 +735,32 @@ fn ui(frame: &mut Frame, app: &mut App, ctx: &mut ClipboardContext) {
         .highlight_symbol("> ");
     app.items.vertical_scroll_state = app.items.vertical_scroll_state.content_length(items.len());
 
-    frame.render_stateful_widget(items, main_layout[1], &mut app.items.state);
+    frame.render_stateful_widget(items, main_layout[2], &mut app.items.state);
 
     frame.render_stateful_widget(
         Scrollbar::new(ScrollbarOrientation::VerticalRight),
-        main_layout[1],
+        main_layout[2],
         &mut app.items.vertical_scroll_state,
     );
-    let selected_item_index = app.items.state.selected();
-    if selected_item_index.is_none() {
-        let selected_item = "Please, select a image file to extract its colors.";
-        frame.render_widget(Paragraph::new(selected_item.bold()), main_layout[2]);
-    } else {
-        let selected_item_index = selected_item_index.unwrap();
-        let selected_item = (&app.items.items[selected_item_index]).to_string();
+    if let Some(selected_item_index) = app.items.state.selected() {
+        let selected_item = app.items.items[selected_item_index].to_string();
 
-        let colors_result = app.images_paths.iter().nth(selected_item_index).unwrap();
-        let colors_result = colors_result.1.to_owned();
+        // Groups show their representative palette
+        let (file_path, colors_result) = match &app.rows[selected_item_index] {
+            ListRow::Image(path) => (Some(path.clone()), app.images_paths[path].to_owned()),
+            ListRow::Group(group_index) => (None, app.groups[*group_index].palette.to_owned()),
+        };
         if let Err(error_message) = colors_result {
             frame.render_widget(
                 Paragraph::new(error_message)
                     .alignment(Alignment::Center)
                     .wrap(Wrap { trim: true })
                     .block(Block::default().title("Error").borders(Borders::ALL)),
-                main_layout[2],
+                main_layout[3],
             );
         } else {
-            let colors = colors_result.unwrap();
-            let cv = ColorsCanvas::new(colors, false, app.with_rgb, app.items.clip_color);
+            let palette = colors_result.unwrap();
+            let cv = ColorsCanvas::new(palette.colors, false, app.with_rgb, app.items.clip_color);
             let duration = Duration::from_secs(3); // clipboarding message duration
 
             let mut item = if !app.items.select_less
@@ -487,69 +768,72 @@ fn ui(frame: &mut Frame, app: &mut App, ctx: &mut ClipboardContext) {
                 && app.items.clip_color
                 && app.items.get_start_time().elapsed() <= duration
             {
-                ctx.set_contents(cv.colors_clipboarded().unwrap()).unwrap();
-                format!("{} - copied to clipboard!", selected_item)
+                if copy_to_clipboard(ctx, cv.colors_clipboarded().unwrap()) {
+                    format!("{} - copied to clipboard!", selected_item)
+                } else {
+                    format!("{} - no clipboard available!", selected_item)
+                }
             } else {
                 selected_item.to_string()
             };
             let cv_b;
-            let tui_text = if !app.items.select_less && !app.items.select_more {
-                app.nb_extracted_colors = app.nb_colors;
-                cv.tui_text()
-            } else {
-                // Manage more/less colors extraction
-                let file_path = app
-                    .images_paths
-                    .iter()
-                    .nth(selected_item_index)
-                    .unwrap()
-                    .0
-                    .to_owned();
-                let file_p = file_path.clone();
-                let image_file = ImageFile::new(file_path);
-                if image_file.image.is_err() {
-                    let error_message = match image_file.image.err().unwrap() {
-                        image::ImageError::IoError(io_error) => match io_error.kind() {
-                            io::ErrorKind::NotFound => "File not found.",
-                            _ => "Error while opening the file!",
-                        },
-                        _ => "Error while opening the file!",
-                    };
-                    let error_message = format!("{}: {}", file_p, error_message);
-                    vec![Span::raw(error_message)]
-                } else {
-                    let colors = image_file.get_colors_from_images(
-                        app.nb_extracted_colors,
-                        app.excluded_colors,
-                        app.bc_color,
-                    );
-                    if let Err(error_message) = colors.as_ref() {
-                        vec![Span::raw(error_message)];
+            let tui_text = match (file_path, app.items.select_less || app.items.select_more) {
+                (Some(_), true) => {
+                    // Manage more/less colors extraction
+                    app.update_extracted_palette();
+                    let more_less_palette = app.extracted_palette.as_ref().unwrap().1.clone();
+                    match more_less_palette {
+                        Err(error_message) => vec![Span::raw(error_message)],
+                        Ok(more_less_palette) => {
+                            cv_b = ColorsCanvas::new(
+                                more_less_palette.colors,
+                                false,
+                                app.with_rgb,
+                                app.items.clip_color,
+                            );
+                            // Manage clip boarding
+                            if app.items.clip_color
+                                && app.items.get_start_time().elapsed() <= duration
+                            {
+                                item = if copy_to_clipboard(ctx, cv_b.colors_clipboarded().unwrap())
+                                {
+                                    format!("{} - copied to clipboard !", selected_item)
+                                } else {
+                                    format!("{} - no clipboard available!", selected_item)
+                                };
+                            };
+                            cv_b.tui_text()
+                        }
                     }
-
-                    let colors = colors.unwrap();
-                    cv_b = ColorsCanvas::new(colors, false, app.with_rgb, app.items.clip_color);
-                    // Manage clip boarding
-                    if app.items.clip_color && app.items.get_start_time().elapsed() <= duration {
-                        ctx.set_contents(cv_b.colors_clipboarded().unwrap())
-                            .unwrap();
-                        item = format!("{} - copied to clipboard !", selected_item);
-                    };
-                    cv_b.tui_text()
+                }
+                _ => {
+                    app.nb_extracted_colors = app.nb_colors;
+                    cv.tui_text()
                 }
             };
-            let colors_extraction_canva = vec![
+            let mut colors_extraction_canva = vec![
                 Line::from(vec![]),
                 Line::from(vec![Span::raw(item)]),
                 Line::from(vec![]),
                 Line::from(tui_text),
             ];
+            let cv_background;
+            if !palette.background.is_empty() {
+                cv_background = ColorsCanvas::new(palette.background, false, app.with_rgb, false);
+                let mut background_text = vec![Span::raw("Background: ")];
+                background_text.extend(cv_background.tui_text());
+                colors_extraction_canva.push(Line::from(vec![]));
+                colors_extraction_canva.push(Line::from(background_text));
+            }
             frame.render_widget(
                 Paragraph::new(colors_extraction_canva)
                     .alignment(Alignment::Center)
                     .wrap(Wrap { trim: true }),
-                main_layout[2],
+                main_layout[3],
             );
         }
+    } else {
+        let selected_item = "Please, select a image file to extract its colors.";
+        frame.render_widget(Paragraph::new(selected_item.bold()), main_layout[3]);
     }
 }
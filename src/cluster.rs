@@ -0,0 +1,234 @@
+use std::cmp::Reverse;
+
+use crate::aggregate::{aggregate_palette, ImagesWeights};
+use crate::images_batch::ImagesColors;
+use crate::near::palettes_distance;
+use crate::{ExtractionOptions, Palette};
+
+/// When to stop merging the groups of images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClusterCriterion {
+    /// Number of groups
+    Count(usize),
+    /// Maximum average ΔE distance between the images of two merged groups
+    Threshold(f32),
+}
+
+/// Images with similar palettes
+#[derive(Debug, Clone)]
+pub struct ImagesGroup {
+    /// Representative palette, aggregated from the images ones
+    pub palette: Result<Palette, String>,
+    pub paths: Vec<String>,
+}
+
+/// Group the images by palette similarity, with an average-linkage agglomerative clustering
+/// The groups are sorted from the largest, the failed images are left out.
+pub fn cluster_images(
+    images_colors_map: &ImagesColors,
+    criterion: ClusterCriterion,
+    nb_colors: u8,
+    options: &ExtractionOptions,
+) -> Vec<ImagesGroup> {
+    let images = images_colors_map
+        .iter()
+        .filter_map(|(path, colors_result)| Some((path, colors_result.as_ref().ok()?)))
+        .collect::<Vec<(&String, &Palette)>>();
+    let nb_images = images.len();
+    let mut distances = PairDistances::new(nb_images);
+    for i in 0..nb_images {
+        for j in i + 1..nb_images {
+            distances.set(i, j, palettes_distance(images[i].1, images[j].1));
+        }
+    }
+    let mut merges = nearest_neighbor_chain(distances);
+    // The average linkage is monotonic, so the merges sorted by distance
+    // are the ones of the closest pairs of groups, merged one at a time
+    merges.sort_by(|merge1, merge2| merge1.0.total_cmp(&merge2.0));
+    let nb_merges = match criterion {
+        ClusterCriterion::Count(count) => nb_images.saturating_sub(count.max(1)),
+        ClusterCriterion::Threshold(threshold) => merges
+            .iter()
+            .take_while(|(distance, _, _)| *distance <= threshold)
+            .count(),
+    };
+    let mut roots = (0..nb_images).collect::<Vec<usize>>();
+    for (_, i, j) in &merges[..nb_merges] {
+        let (root_i, root_j) = (find_root(&mut roots, *i), find_root(&mut roots, *j));
+        roots[root_j] = root_i;
+    }
+    let mut groups = vec![vec![]; nb_images];
+    for index in 0..nb_images {
+        let root = find_root(&mut roots, index);
+        groups[root].push(index);
+    }
+
+    let mut images_groups = groups
+        .into_iter()
+        .filter(|group| !group.is_empty())
+        .map(|group| {
+            let group_colors = group
+                .iter()
+                .map(|index| (images[*index].0.clone(), Ok(images[*index].1.clone())))
+                .collect::<ImagesColors>();
+            ImagesGroup {
+                palette: aggregate_palette(
+                    &group_colors,
+                    nb_colors,
+                    options,
+                    ImagesWeights::Pixels,
+                ),
+                paths: group_colors.into_keys().collect(),
+            }
+        })
+        .collect::<Vec<ImagesGroup>>();
+    images_groups.sort_by_key(|group| Reverse(group.paths.len()));
+    images_groups
+}
+
+/// Distances between the pairs of images, stored once per pair
+struct PairDistances {
+    nb_images: usize,
+    distances: Vec<f32>,
+}
+
+impl PairDistances {
+    fn new(nb_images: usize) -> Self {
+        PairDistances {
+            nb_images,
+            distances: vec![0.0; nb_images * nb_images.saturating_sub(1) / 2],
+        }
+    }
+
+    fn index(&self, i: usize, j: usize) -> usize {
+        let (i, j) = (i.min(j), i.max(j));
+        i * (2 * self.nb_images - i - 1) / 2 + j - i - 1
+    }
+
+    fn get(&self, i: usize, j: usize) -> f32 {
+        self.distances[self.index(i, j)]
+    }
+
+    fn set(&mut self, i: usize, j: usize, distance: f32) {
+        let index = self.index(i, j);
+        self.distances[index] = distance;
+    }
+}
+
+/// Merges (distance and images of each group) of the average-linkage clustering,
+/// found with a nearest-neighbor chain in a quadratic time
+/// A merged group is kept under the index of its second image.
+fn nearest_neighbor_chain(mut distances: PairDistances) -> Vec<(f32, usize, usize)> {
+    let nb_images = distances.nb_images;
+    let mut sizes = vec![1; nb_images];
+    let mut is_active = vec![true; nb_images];
+    let mut merges = Vec::with_capacity(nb_images.saturating_sub(1));
+    let mut chain: Vec<usize> = vec![];
+    while merges.len() + 1 < nb_images {
+        if chain.is_empty() {
+            chain.extend(is_active.iter().position(|active| *active));
+        }
+        let group = chain[chain.len() - 1];
+        // The previous group of the chain wins the ties, so that the chain ends
+        let previous = chain.len().checked_sub(2).map(|index| chain[index]);
+        let (mut nearest, mut distance) = match previous {
+            Some(previous) => (Some(previous), distances.get(group, previous)),
+            None => (None, f32::INFINITY),
+        };
+        for other in (0..nb_images).filter(|other| is_active[*other] && *other != group) {
+            if distances.get(group, other) < distance {
+                (nearest, distance) = (Some(other), distances.get(group, other));
+            }
+        }
+        let Some(nearest) = nearest else {
+            break;
+        };
+        if Some(nearest) != previous {
+            chain.push(nearest);
+            continue;
+        }
+        // Reciprocal nearest neighbors: average distances of the merged group
+        chain.truncate(chain.len() - 2);
+        let (size_group, size_nearest) = (sizes[group] as f32, sizes[nearest] as f32);
+        for other in (0..nb_images).filter(|other| is_active[*other]) {
+            if other != group && other != nearest {
+                let merged_distance = (size_group * distances.get(group, other)
+                    + size_nearest * distances.get(nearest, other))
+                    / (size_group + size_nearest);
+                distances.set(nearest, other, merged_distance);
+            }
+        }
+        is_active[group] = false;
+        sizes[nearest] += sizes[group];
+        merges.push((distance, group, nearest));
+    }
+    merges
+}
+
+/// Root image of a group, compressing the path to it
+fn find_root(roots: &mut [usize], index: usize) -> usize {
+    let mut root = index;
+    while roots[root] != root {
+        root = roots[root];
+    }
+    let mut index = index;
+    while roots[index] != root {
+        (roots[index], index) = (root, roots[index]);
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_thief::Color;
+
+    fn images_colors() -> ImagesColors {
+        [
+            ("dark1", (10, 10, 10)),
+            ("red1", (250, 0, 0)),
+            ("dark2", (0, 0, 0)),
+            ("red2", (240, 10, 10)),
+            ("blue", (0, 0, 250)),
+        ]
+        .into_iter()
+        .map(|(path, (r, g, b))| {
+            let palette = Palette {
+                colors: vec![Color { r, g, b }],
+                proportions: vec![1.0],
+                pixels: 100,
+                ..Palette::default()
+            };
+            (path.to_string(), Ok(palette))
+        })
+        .collect()
+    }
+
+    fn groups_paths(criterion: ClusterCriterion) -> Vec<Vec<String>> {
+        let options = ExtractionOptions::default();
+        let mut groups_paths = cluster_images(&images_colors(), criterion, 2, &options)
+            .into_iter()
+            .map(|group| group.paths)
+            .collect::<Vec<Vec<String>>>();
+        groups_paths.sort();
+        groups_paths
+    }
+
+    #[test]
+    fn closest_groups() {
+        let paths =
+            |paths: &[&str]| -> Vec<String> { paths.iter().map(|path| path.to_string()).collect() };
+        let similar_groups = vec![
+            paths(&["blue"]),
+            paths(&["dark1", "dark2"]),
+            paths(&["red1", "red2"]),
+        ];
+        assert_eq!(groups_paths(ClusterCriterion::Count(3)), similar_groups);
+        assert_eq!(
+            groups_paths(ClusterCriterion::Threshold(10.0)),
+            similar_groups
+        );
+        assert_eq!(groups_paths(ClusterCriterion::Count(1)).len(), 1);
+        assert_eq!(groups_paths(ClusterCriterion::Threshold(0.0)).len(), 5);
+    }
+}
@@ -1,9 +1,12 @@
-use crate::process;
 use crate::{Color, ColorFormat};
+#[cfg(feature = "native")]
 use crossterm::style::Color as TerminalColor;
-use regex::Regex;
 use std::borrow::Borrow; // Vector borrowing
-use std::error;
+
+// D65 reference white
+const WHITE_X: f32 = 0.95047;
+const WHITE_Y: f32 = 1.0;
+const WHITE_Z: f32 = 1.08883;
 
 pub trait ColorTrait {
     fn hexadecimal_str(&self) -> String;
@@ -11,10 +14,15 @@ pub trait ColorTrait {
     fn color_brightness(&self) -> f32;
     fn best_contrast<'a, T: Borrow<Color>>(&'a self, colors: &'a [T]) -> &'a T;
     fn contrast_with(&self, col: Color) -> f32;
+    fn relative_luminance(&self) -> f32;
+    fn contrast_ratio(&self, col: Color) -> f32;
     fn to_slice(&self) -> [u8; 3];
-    fn hex_to_rgb(hex_code: &str) -> Result<Color, Box<dyn error::Error>>;
+    fn hex_to_rgb(hex_code: &str) -> Result<Color, String>;
     fn pixels_to_rbg(arr: &[u8], color_format: ColorFormat) -> Color;
     fn delta_rgb(&self, col2: Color) -> f32;
+    fn to_hsl(&self) -> [f32; 3];
+    fn to_lab(&self) -> [f32; 3];
+    #[cfg(feature = "native")]
     fn to_term_color(&self) -> TerminalColor;
 }
 
@@ -56,20 +64,39 @@ impl ColorTrait for Color {
         (self.color_brightness() - col.color_brightness()).abs()
     }
 
+    // https://www.w3.org/TR/WCAG21/#dfn-relative-luminance
+    fn relative_luminance(&self) -> f32 {
+        let linear = |channel: u8| {
+            let channel = channel as f32 / 255.0;
+            if channel <= 0.04045 {
+                channel / 12.92
+            } else {
+                ((channel + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    // WCAG contrast ratio, from 1 to 21
+    fn contrast_ratio(&self, col: Color) -> f32 {
+        let (l1, l2) = (self.relative_luminance(), col.relative_luminance());
+        (l1.max(l2) + 0.05) / (l1.min(l2) + 0.05)
+    }
+
     fn to_slice(&self) -> [u8; 3] {
         [self.r, self.g, self.b]
     }
 
-    fn hex_to_rgb(hex_code: &str) -> Result<Color, Box<dyn error::Error>> {
-        let hex_regex = Regex::new("^(?i)#[\\da-f]{6}$").unwrap();
+    // Hexadecimal code, with or without #
+    fn hex_to_rgb(hex_code: &str) -> Result<Color, String> {
         let hex_code = hex_code.trim();
-        if !hex_regex.is_match(hex_code) {
-            eprintln !("{hex_code} is not a valid hexadecimal code.\nPlease provide a valid hex code, and try again!");
-            process::exit(1);
+        let digits = hex_code.strip_prefix('#').unwrap_or(hex_code);
+        if digits.len() != 6 || !digits.chars().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(format!("{hex_code} is not a valid hexadecimal code."));
         }
-        let r = u8::from_str_radix(&hex_code[1..3], 16).unwrap();
-        let g = u8::from_str_radix(&hex_code[3..5], 16).unwrap();
-        let b = u8::from_str_radix(&hex_code[5..7], 16).unwrap();
+        let r = u8::from_str_radix(&digits[0..2], 16).unwrap();
+        let g = u8::from_str_radix(&digits[2..4], 16).unwrap();
+        let b = u8::from_str_radix(&digits[4..6], 16).unwrap();
         Ok(Color { r, g, b })
     }
 
@@ -105,14 +132,68 @@ impl ColorTrait for Color {
 
     // https://gist.github.com/ryancat/9972419b2a78f329ce3aebb7f1a09152
     fn delta_rgb(&self, col2: Color) -> f32 {
-        let drp2 = (self.r as f32 - col2.r as f32).powf(2.0) as f32;
-        let dgp2 = (self.g as f32 - col2.g as f32).powf(2.0) as f32;
-        let dbp2 = (self.b as f32 - col2.b as f32).powf(2.0) as f32;
-        let t = (self.r as f32 + col2.r as f32) as f32 / 2.0;
+        let drp2 = (self.r as f32 - col2.r as f32).powf(2.0);
+        let dgp2 = (self.g as f32 - col2.g as f32).powf(2.0);
+        let dbp2 = (self.b as f32 - col2.b as f32).powf(2.0);
+        let t = (self.r as f32 + col2.r as f32) / 2.0;
 
         2.0 * drp2 + 4.0 * dgp2 + 3.0 * dbp2 + t * (drp2 - dbp2) / 256.0
     }
 
+    // Hue in degrees, saturation and lightness between 0 and 1
+    fn to_hsl(&self) -> [f32; 3] {
+        let (r, g, b) = (
+            self.r as f32 / 255.0,
+            self.g as f32 / 255.0,
+            self.b as f32 / 255.0,
+        );
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let chroma = max - min;
+        let lightness = (max + min) / 2.0;
+        if chroma == 0.0 {
+            return [0.0, 0.0, lightness];
+        }
+        let hue = if max == r {
+            60.0 * ((g - b) / chroma).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / chroma + 2.0)
+        } else {
+            60.0 * ((r - g) / chroma + 4.0)
+        };
+        [
+            hue,
+            chroma / (1.0 - (2.0 * lightness - 1.0).abs()),
+            lightness,
+        ]
+    }
+
+    // CIELAB coordinates of the sRGB color
+    fn to_lab(&self) -> [f32; 3] {
+        let linear = |channel: u8| {
+            let channel = channel as f32 / 255.0;
+            if channel <= 0.04045 {
+                channel / 12.92
+            } else {
+                ((channel + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        let (r, g, b) = (linear(self.r), linear(self.g), linear(self.b));
+        let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / WHITE_X;
+        let y = (0.2126 * r + 0.7152 * g + 0.0722 * b) / WHITE_Y;
+        let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / WHITE_Z;
+        let f = |t: f32| {
+            if t > 216.0 / 24389.0 {
+                t.cbrt()
+            } else {
+                (24389.0 / 27.0 * t + 16.0) / 116.0
+            }
+        };
+        let (fx, fy, fz) = (f(x), f(y), f(z));
+        [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+    }
+
+    #[cfg(feature = "native")]
     fn to_term_color(&self) -> TerminalColor {
         TerminalColor::Rgb {
             r: self.r,
@@ -8,6 +8,8 @@ use ratatui::{
 };
 use std::io;
 
+const STRIP_HEIGHT: usize = 2;
+
 pub struct ColorsCanvas {
     colors: Vec<Color>,
     show_canvas: bool,
@@ -125,8 +127,34 @@ impl ColorsCanvas {
         }
     }
 
+    /// Animation frame colors displaying, as a strip row in canvas
+    pub fn display_frame(&self, label: &str) {
+        if !self.show_canvas {
+            print!("{label} ");
+            self.print();
+            return;
+        }
+        let (term_w, _) = crossterm::terminal::size().unwrap();
+        let label_w = label.len() + 1;
+        let strip_w = (term_w as usize).saturating_sub(label_w);
+        let cell_w = strip_w / self.colors.len().max(1);
+        for row in 0..STRIP_HEIGHT {
+            if row == STRIP_HEIGHT / 2 {
+                io::stdout()
+                    .execute(Print(format!("{label:<label_w$}").bold()))
+                    .unwrap();
+            } else {
+                print!("{}", " ".repeat(label_w));
+            }
+            for col in &self.colors {
+                stylize_text(" ".repeat(cell_w), false, col, col);
+            }
+            println!();
+        }
+    }
+
     /// TUI Colors displaying
-    pub fn tui_text(&self) -> Vec<Span> {
+    pub fn tui_text(&self) -> Vec<Span<'_>> {
         let mut colors_items = Vec::<Span>::with_capacity(self.colors.len() + 1);
         let t_colors = vec![
             Color { r: 0, g: 0, b: 0 },
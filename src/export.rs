@@ -0,0 +1,93 @@
+use color_thief::Color;
+use serde_json::{json, Value};
+
+use crate::ColorTrait;
+
+/// Palette file formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// GIMP palette
+    Gpl,
+    /// CSS custom properties
+    Css,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "gpl" => Ok(ExportFormat::Gpl),
+            "css" => Ok(ExportFormat::Css),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!(
+                "{name} is not a valid export format. Possible values: gpl, css, json."
+            )),
+        }
+    }
+}
+
+/// Color in the hexadecimal, RGB, HSL and CIELAB formats, as JSON
+pub fn export_color(color: &Color) -> Value {
+    let round = |value: f32, factor: f64| (value as f64 * factor).round() / factor;
+    let [hue, saturation, lightness] = color.to_hsl();
+    json!({
+        "hex": color.hexadecimal_str(),
+        "rgb": color.to_slice(),
+        "hsl": [round(hue, 10.0), round(saturation * 100.0, 10.0), round(lightness * 100.0, 10.0)],
+        "lab": color.to_lab().map(|channel| round(channel, 100.0)),
+    })
+}
+
+/// Palette file content of the colors, in that format
+pub fn export_colors(colors: &[Color], name: &str, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Gpl => {
+            let mut gpl = format!("GIMP Palette\nName: {name}\nColumns: {}\n#\n", colors.len());
+            for color in colors {
+                gpl.push_str(&format!(
+                    "{:3} {:3} {:3}\t{}\n",
+                    color.r,
+                    color.g,
+                    color.b,
+                    color.hexadecimal_str()
+                ));
+            }
+            gpl
+        }
+        ExportFormat::Css => {
+            let prefix = css_identifier(name);
+            let mut css = String::from(":root {\n");
+            for (index, color) in colors.iter().enumerate() {
+                css.push_str(&format!(
+                    "  --{prefix}-{}: {};\n",
+                    index + 1,
+                    color.hexadecimal_str()
+                ));
+            }
+            css.push_str("}\n");
+            css
+        }
+        ExportFormat::Json => {
+            let hex_colors = colors
+                .iter()
+                .map(|color| color.hexadecimal_str())
+                .collect::<Vec<String>>();
+            format!("{:#}\n", json!({ "name": name, "colors": hex_colors }))
+        }
+    }
+}
+
+/// Lowercase CSS identifier of a palette name, words joined by dashes
+fn css_identifier(name: &str) -> String {
+    let identifier = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+        .to_lowercase();
+    match identifier.chars().next() {
+        None => "color".to_string(),
+        Some(first) if first.is_ascii_digit() => format!("color-{identifier}"),
+        Some(_) => identifier,
+    }
+}
@@ -0,0 +1,177 @@
+use crate::color_trait::ColorTrait;
+use crate::frames::FramesSelector;
+use crate::mask::Mask;
+#[cfg(feature = "native")]
+use crate::palette_cache::PaletteCache;
+#[cfg(feature = "native")]
+use crate::remote::DownloadOptions;
+use crate::svg::{DEFAULT_SVG_SIZE, MAX_SVG_SIZE};
+use crate::weighting::Weighting;
+use color_thief::Color;
+use serde_json::Value;
+use std::borrow::Cow;
+
+// Number of colors of the requests which do not specify it
+const DEFAULT_NB_COLORS: u8 = 5;
+
+/// Extraction settings shared by the single file,
+/// the repertory and the TUI extractions
+#[derive(Debug, Clone)]
+pub struct ExtractionOptions {
+    pub excluded_colors: Vec<Color>,
+    pub bc_color: Option<Color>,
+    pub mask: Option<Mask>,
+    pub ignore_background: bool,
+    pub weighting: Option<Weighting>,
+    /// Frames of animated images, merged in a single palette
+    pub frames: Option<FramesSelector>,
+    /// Larger side of the rasterized SVG images
+    pub svg_size: u32,
+    /// Exact painted colors of the SVG images, instead of rasterizing them
+    pub svg_native: bool,
+    /// Remote images downloading settings
+    #[cfg(feature = "native")]
+    pub download: DownloadOptions,
+    /// Cache of the extracted palettes
+    #[cfg(feature = "native")]
+    pub palette_cache: Option<PaletteCache>,
+}
+
+impl Default for ExtractionOptions {
+    fn default() -> Self {
+        ExtractionOptions {
+            excluded_colors: vec![],
+            bc_color: None,
+            mask: None,
+            ignore_background: false,
+            weighting: None,
+            frames: None,
+            svg_size: DEFAULT_SVG_SIZE,
+            svg_native: false,
+            #[cfg(feature = "native")]
+            download: DownloadOptions::default(),
+            #[cfg(feature = "native")]
+            palette_cache: None,
+        }
+    }
+}
+
+impl ExtractionOptions {
+    /// Settings changing the extracted palette, for the palettes index
+    /// The version is included, as the extraction can change between versions.
+    pub fn settings_key(&self, nb_colors: u8) -> String {
+        format!(
+            "{}|{nb_colors}|{:?}|{:?}|{:?}|{}|{:?}|{:?}|{}|{}",
+            env!("CARGO_PKG_VERSION"),
+            self.excluded_colors,
+            self.bc_color,
+            self.mask,
+            self.ignore_background,
+            self.weighting,
+            self.frames,
+            self.svg_size,
+            self.svg_native,
+        )
+    }
+
+    /// Settings and mask file state of an image extraction, for the palettes caches,
+    /// so that the images are extracted again with an edited mask
+    #[cfg(feature = "native")]
+    pub fn cache_key(&self, nb_colors: u8, image_path: &str) -> String {
+        let mask_state = self
+            .mask
+            .as_ref()
+            .and_then(|mask| mask.file_state(image_path));
+        format!("{}|{mask_state:?}", self.settings_key(nb_colors))
+    }
+
+    /// Number of colors and options of the requests parameters, named like the command
+    /// line options and applied over these options
+    /// The flags are set unless their value is false, 0 or no, unknown parameters are refused.
+    pub fn with_parameters<'a>(
+        &self,
+        parameters: impl Iterator<Item = (Cow<'a, str>, Cow<'a, str>)>,
+    ) -> Result<(u8, Self), String> {
+        let mut options = self.clone();
+        let mut nb_colors = DEFAULT_NB_COLORS;
+        let is_set = |value: &str| !matches!(value, "false" | "0" | "no");
+        for (key, value) in parameters {
+            match key.as_ref() {
+                "n" | "nb-colors" => {
+                    nb_colors = match value.parse::<u8>() {
+                        Ok(nb) if nb > 1 => nb,
+                        _ => {
+                            return Err(
+                                "The number of colors should be between 2 and 255.".to_string()
+                            )
+                        }
+                    }
+                }
+                "exc-colors" => {
+                    for hex in value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|hex| !hex.is_empty())
+                    {
+                        options.excluded_colors.push(Color::hex_to_rgb(hex)?);
+                    }
+                }
+                "bcw" => {
+                    if is_set(&value) {
+                        options.bc_color = Some(Color {
+                            r: 255,
+                            g: 255,
+                            b: 255,
+                        })
+                    }
+                }
+                "bcb" => {
+                    if is_set(&value) {
+                        options.bc_color = Some(Color { r: 0, g: 0, b: 0 })
+                    }
+                }
+                "ignore-background" => options.ignore_background = is_set(&value),
+                "weighting" => options.weighting = Some(Weighting::from_name(&value)?),
+                "frames" => options.frames = Some(FramesSelector::from_name(&value)?),
+                "aggregate-frames" => {
+                    if is_set(&value) {
+                        options.frames = options.frames.or(Some(FramesSelector::All))
+                    }
+                }
+                "svg-size" => options.svg_size = parse_svg_size(&value)?,
+                "svg-native" => options.svg_native = is_set(&value),
+                _ => return Err(format!("Unknown option: {key}.")),
+            }
+        }
+        if options.svg_native && (options.ignore_background || options.weighting.is_some()) {
+            return Err(
+                "The svg-native option cannot be combined with ignore-background nor weighting."
+                    .to_string(),
+            );
+        }
+        Ok((nb_colors, options))
+    }
+}
+
+/// Larger side of the rasterized SVG images, up to the maximum size
+pub fn parse_svg_size(value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(size) if size > 0 && size <= MAX_SVG_SIZE => Ok(size),
+        _ => Err(format!(
+            "The SVG size should be a positive number of pixels, up to {MAX_SVG_SIZE}."
+        )),
+    }
+}
+
+/// Option value of a JSON parameter, the arrays being comma separated
+pub fn parameter_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(parameter_value)
+            .collect::<Vec<String>>()
+            .join(","),
+        value => value.to_string(),
+    }
+}
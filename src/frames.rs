@@ -0,0 +1,132 @@
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    AnimationDecoder, DynamicImage, Frames, ImageFormat, ImageResult,
+};
+use std::io::{BufReader, Cursor, Read, Seek};
+
+// Browsers show frames without delay during 100 ms
+const DEFAULT_FRAME_DURATION_MS: f32 = 100.0;
+
+/// Frames selection of an animated image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramesSelector {
+    All,
+    /// Only the frame N (counted from 1)
+    Nth(usize),
+    /// One frame every K frames, from the first one
+    Every(usize),
+}
+
+impl FramesSelector {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        let invalid_selector =
+            || format!("{name} is not a valid frames selection. Possible values: all, N, every:K.");
+        let name = name.trim().to_lowercase();
+        if name == "all" {
+            return Ok(FramesSelector::All);
+        }
+        let (every, number) = match name.strip_prefix("every:") {
+            Some(k) => (true, k),
+            None => (false, name.as_str()),
+        };
+        match number.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(if every {
+                FramesSelector::Every(n)
+            } else {
+                FramesSelector::Nth(n)
+            }),
+            _ => Err(invalid_selector()),
+        }
+    }
+
+    // usize::is_multiple_of would require Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    fn keep(&self, index: usize) -> bool {
+        match self {
+            FramesSelector::All => true,
+            FramesSelector::Nth(n) => index + 1 == *n,
+            FramesSelector::Every(k) => index % k == 0,
+        }
+    }
+}
+
+/// Selected frame of an animated image
+pub struct AnimationFrame {
+    /// Frame number, counted from 1
+    pub number: usize,
+    pub duration_ms: f32,
+    pub image: DynamicImage,
+}
+
+impl AnimationFrame {
+    /// Single frame of a still image
+    pub fn still(image: DynamicImage) -> Self {
+        AnimationFrame {
+            number: 1,
+            duration_ms: DEFAULT_FRAME_DURATION_MS,
+            image,
+        }
+    }
+}
+
+/// Selected frames of an animated GIF, APNG or WebP image
+/// None for still images.
+fn decode_animation_frames<R: Read + Seek>(
+    reader: R,
+    format: ImageFormat,
+    selector: FramesSelector,
+) -> ImageResult<Option<Vec<AnimationFrame>>> {
+    let reader = BufReader::new(reader);
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(reader)?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(reader)?;
+            if !decoder.is_apng() {
+                return Ok(None);
+            }
+            decoder.apng().into_frames()
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(reader)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    select_frames(frames, selector).map(Some)
+}
+
+/// Selected frames of an encoded animated image, None for still images
+pub fn decode_frames(
+    bytes: &[u8],
+    selector: FramesSelector,
+) -> ImageResult<Option<Vec<AnimationFrame>>> {
+    let format = image::guess_format(bytes)?;
+    decode_animation_frames(Cursor::new(bytes), format, selector)
+}
+
+fn select_frames(frames: Frames, selector: FramesSelector) -> ImageResult<Vec<AnimationFrame>> {
+    let mut selected_frames = vec![];
+    for (index, frame) in frames.enumerate() {
+        if !selector.keep(index) {
+            continue;
+        }
+        let frame = frame?;
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let duration_ms = match numer as f32 / denom.max(1) as f32 {
+            d if d > 0.0 => d,
+            _ => DEFAULT_FRAME_DURATION_MS,
+        };
+        selected_frames.push(AnimationFrame {
+            number: index + 1,
+            duration_ms,
+            image: DynamicImage::ImageRgba8(frame.into_buffer()),
+        });
+        if let FramesSelector::Nth(_) = selector {
+            break;
+        }
+    }
+    Ok(selected_frames)
+}
@@ -0,0 +1,204 @@
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+const BODY_EXTENSION: &str = "body";
+const META_EXTENSION: &str = "json";
+// Share of the cap left after an eviction, so that the next entries
+// are cached without scanning the directory again
+pub const EVICTION_TARGET_PERCENT: u64 = 90;
+
+/// Cached response of a remote image
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CachedResponse {
+    pub data: Vec<u8>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// URL after the redirections, if redirected
+    pub final_url: Option<String>,
+}
+
+/// On-disk cache of the remote images, keyed by URL and by the credentials
+/// they were requested with
+/// The least recently used entries are evicted above the size cap.
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    dir: PathBuf,
+    /// Maximum size of the cached bodies, in bytes
+    max_size: u64,
+    /// Size of the cached bodies, read from the directory once then tracked
+    size: Arc<Mutex<Option<u64>>>,
+}
+
+impl HttpCache {
+    pub fn new(dir: PathBuf, max_size: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(HttpCache {
+            dir,
+            max_size,
+            size: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// Entry path of the URL requested with these credentials (headers and login),
+    /// so that a response is never served to another identity
+    fn entry_path(&self, url: &str, credentials: &str, extension: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(credentials.as_bytes());
+        hasher.update([0]);
+        hasher.update(url.as_bytes());
+        let key = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        self.dir.join(key).with_extension(extension)
+    }
+
+    /// Cached response of the URL, marked as recently used
+    pub fn get(&self, url: &str, credentials: &str) -> Option<CachedResponse> {
+        let body_path = self.entry_path(url, credentials, BODY_EXTENSION);
+        let meta = fs::read_to_string(self.entry_path(url, credentials, META_EXTENSION)).ok()?;
+        let meta: Value = serde_json::from_str(&meta).ok()?;
+        // Another URL with the same hash is a miss
+        if meta["url"].as_str() != Some(url) {
+            return None;
+        }
+        let data = fs::read(&body_path).ok()?;
+        if let Ok(body_file) = File::options().write(true).open(&body_path) {
+            let _ = body_file.set_modified(SystemTime::now());
+        }
+        let meta_str = |key: &str| meta[key].as_str().map(String::from);
+        Some(CachedResponse {
+            data,
+            content_type: meta_str("content_type"),
+            etag: meta_str("etag"),
+            last_modified: meta_str("last_modified"),
+            final_url: meta_str("final_url"),
+        })
+    }
+
+    /// Cache the response of the URL, then evict the oldest entries above the cap
+    pub fn put(&self, url: &str, credentials: &str, response: &CachedResponse) -> io::Result<()> {
+        if response.data.len() as u64 > self.max_size {
+            return Ok(());
+        }
+        let body_path = self.entry_path(url, credentials, BODY_EXTENSION);
+        let replaced_size = fs::metadata(&body_path).map_or(0, |metadata| metadata.len());
+        let meta = json!({
+            "url": url,
+            "content_type": response.content_type,
+            "etag": response.etag,
+            "last_modified": response.last_modified,
+            "final_url": response.final_url,
+        });
+        write_atomically(&body_path, &response.data)?;
+        write_atomically(
+            &self.entry_path(url, credentials, META_EXTENSION),
+            meta.to_string().as_bytes(),
+        )?;
+        // The directory is only scanned again when the cap is exceeded
+        let mut size = self.size.lock().unwrap();
+        let cache_size = match *size {
+            Some(cache_size) => {
+                cache_size.saturating_sub(replaced_size) + response.data.len() as u64
+            }
+            None => self.bodies()?.iter().map(|(_, size, _)| size).sum(),
+        };
+        *size = Some(if cache_size > self.max_size {
+            self.evict()?
+        } else {
+            cache_size
+        });
+        Ok(())
+    }
+
+    /// Cached bodies, with their last use time and size
+    fn bodies(&self) -> io::Result<Vec<(SystemTime, u64, PathBuf)>> {
+        used_files(&self.dir, BODY_EXTENSION)
+    }
+
+    /// Evict the least recently used entries below the cap, and return the cache size
+    fn evict(&self) -> io::Result<u64> {
+        let mut bodies = self.bodies()?;
+        let mut cache_size: u64 = bodies.iter().map(|(_, size, _)| size).sum();
+        bodies.sort();
+        for (_, size, body_path) in bodies {
+            if cache_size <= self.max_size / 100 * EVICTION_TARGET_PERCENT {
+                break;
+            }
+            // Entries can be evicted by parallel extractions
+            let _ = fs::remove_file(body_path.with_extension(META_EXTENSION));
+            let _ = fs::remove_file(&body_path);
+            cache_size -= size;
+        }
+        Ok(cache_size)
+    }
+}
+
+/// Files of a cache directory with that extension, with their last use time and size
+pub fn used_files(dir: &Path, extension: &str) -> io::Result<Vec<(SystemTime, u64, PathBuf)>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)?.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(extension) {
+            continue;
+        }
+        if let Ok(metadata) = entry.metadata() {
+            let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((used, metadata.len(), path));
+        }
+    }
+    Ok(files)
+}
+
+/// Write through a temporary file, so parallel readers never see a partial file
+pub fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = tempfile::NamedTempFile::new_in(path.parent().unwrap())?;
+    file.write_all(data)?;
+    file.persist(path).map_err(|error| error.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(size: usize) -> CachedResponse {
+        CachedResponse {
+            data: vec![0; size],
+            content_type: Some("image/png".to_string()),
+            ..CachedResponse::default()
+        }
+    }
+
+    #[test]
+    fn credentials_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(dir.path().to_path_buf(), 1000).unwrap();
+        let url = "https://example.com/image.png";
+        cache.put(url, "authorization: a\n", &response(10)).unwrap();
+        assert_eq!(cache.get(url, "authorization: a\n"), Some(response(10)));
+        assert_eq!(cache.get(url, "authorization: b\n"), None);
+        assert_eq!(cache.get(url, ""), None);
+    }
+
+    #[test]
+    fn eviction() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(dir.path().to_path_buf(), 100).unwrap();
+        for index in 0..5 {
+            let url = format!("https://example.com/{index}.png");
+            cache.put(&url, "", &response(40)).unwrap();
+        }
+        assert_eq!(*cache.size.lock().unwrap(), Some(80));
+        assert_eq!(cache.bodies().unwrap().len(), 2);
+    }
+}
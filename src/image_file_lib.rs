@@ -1,70 +1,441 @@
+use crate::frames::{decode_frames, AnimationFrame, FramesSelector};
+use crate::image_trait::{is_excluded_color, MIN_ALPHA};
+use crate::svg::{
+    is_svg_data, is_svg_file, open_svg, rasterize_svg, svg_painted_colors, SVG_MIME_TYPE,
+};
 use crate::ColorTrait;
+use crate::ExtractionOptions;
 use crate::ImageTrait;
+use crate::Palette;
 use color_thief::{Color, ColorFormat};
-use image::{self, DynamicImage, ImageResult};
-use std::{cmp::Ordering, path::Path};
+use image::{self, DynamicImage, ImageFormat, ImageResult};
+use std::{borrow::Cow, cmp::Ordering, fs, io, path::Path, sync::OnceLock};
+
+// One pixel out of 10 is sampled
+const QUANTIZATION_QUALITY: u8 = 10;
 
 #[derive(Debug)]
 pub struct ImageFile {
     file_path: String,
-    pub image: ImageResult<DynamicImage>,
+    /// Encoded image, when it is not read from the file path
+    data: Option<Vec<u8>>,
+    media_type: Option<String>,
+    /// Decoded image, on first use, as the animated images frames are decoded on their own
+    image: OnceLock<ImageResult<DynamicImage>>,
+    svg_size: u32,
 }
 
 impl ImageFile {
-    pub fn new(file_path: String) -> Self {
-        let mut image_file = Self {
+    /// Image file, rasterized at the given size if it is a SVG file
+    pub fn new(file_path: String, svg_size: u32) -> Self {
+        Self {
             file_path,
-            image: Ok(DynamicImage::new_rgb8(0, 0)),
-        };
-        image_file.set_image_from_file_path();
-        image_file
+            data: None,
+            media_type: None,
+            image: OnceLock::new(),
+            svg_size,
+        }
+    }
+
+    /// Image of encoded data, the format is guessed from its content
+    /// or else from its media type, if known.
+    pub fn from_data(name: String, data: Vec<u8>, mime_type: Option<&str>, svg_size: u32) -> Self {
+        Self {
+            file_path: name,
+            data: Some(data),
+            media_type: mime_type.map(str::to_string),
+            image: OnceLock::new(),
+            svg_size,
+        }
+    }
+
+    /// Decoded image, or the decoding error
+    pub fn image(&self) -> &ImageResult<DynamicImage> {
+        self.image.get_or_init(|| self.decode())
     }
 
     pub fn set_image_from_file_path(&mut self) {
-        self.image = image::open(Path::new(&self.file_path));
+        self.image = OnceLock::from(self.decode());
+    }
+
+    fn decode(&self) -> ImageResult<DynamicImage> {
+        let Some(data) = &self.data else {
+            return if is_svg_file(&self.file_path) {
+                open_svg(&self.file_path, self.svg_size)
+            } else {
+                image::open(Path::new(&self.file_path))
+            };
+        };
+        if self.is_svg() {
+            return rasterize_svg(data, self.svg_size);
+        }
+        let mime_format = self
+            .media_type
+            .as_deref()
+            .and_then(ImageFormat::from_mime_type);
+        match (image::guess_format(data), mime_format) {
+            (Err(_), Some(format)) => image::load_from_memory_with_format(data, format),
+            _ => image::load_from_memory(data),
+        }
+    }
+
+    /// Reason the image could not be opened, if any
+    /// With a frames selection, the image is only read, its frames being decoded apart.
+    pub fn open_error(&self, frames: Option<FramesSelector>) -> Option<String> {
+        let error_kind = if frames.is_some() && !self.is_svg() {
+            if self.data.is_some() {
+                return None;
+            }
+            fs::metadata(&self.file_path).err()?.kind()
+        } else {
+            match self.image() {
+                Ok(_) => return None,
+                Err(image::ImageError::IoError(io_error)) => io_error.kind(),
+                Err(_) => io::ErrorKind::InvalidData,
+            }
+        };
+        Some(if error_kind == io::ErrorKind::NotFound {
+            "File not found.\nPlease be sure you provide the correct path!".to_string()
+        } else {
+            "Error while opening the file!".to_string()
+        })
+    }
+
+    fn is_svg(&self) -> bool {
+        match &self.data {
+            Some(data) => is_svg_data(data) || self.media_type.as_deref() == Some(SVG_MIME_TYPE),
+            None => is_svg_file(&self.file_path),
+        }
+    }
+
+    /// Encoded image
+    fn encoded_data(&self) -> io::Result<Cow<'_, [u8]>> {
+        match &self.data {
+            Some(data) => Ok(Cow::Borrowed(data)),
+            None => fs::read(&self.file_path).map(Cow::Owned),
+        }
+    }
+
+    /// Pixels weights of the image, if any
+    /// The mask and the weighting mode weights are multiplied.
+    fn pixels_weights(
+        &self,
+        image: &DynamicImage,
+        options: &ExtractionOptions,
+    ) -> Result<Option<Vec<f32>>, String> {
+        let mask_weights = match &options.mask {
+            Some(mask) => {
+                Some(mask.pixels_weights(&self.file_path, image.width(), image.height())?)
+            }
+            None => None,
+        };
+        let weighting_weights = options
+            .weighting
+            .map(|weighting| weighting.pixels_weights(image));
+        Ok(match (mask_weights, weighting_weights) {
+            (Some(mask_weights), Some(weighting_weights)) => Some(
+                mask_weights
+                    .iter()
+                    .zip(weighting_weights)
+                    .map(|(mw, ww)| mw * ww)
+                    .collect(),
+            ),
+            (mask_weights, weighting_weights) => mask_weights.or(weighting_weights),
+        })
+    }
+
+    /// Kept RGB pixels bytes of an image and its detected background
+    /// The pixels weights are scaled by the weight factor.
+    fn extraction_bytes(
+        &self,
+        image: &DynamicImage,
+        options: &ExtractionOptions,
+        weight_factor: f32,
+    ) -> Result<(Vec<u8>, Vec<Color>), String> {
+        let weights = self.pixels_weights(image, options)?;
+        let weights = match weights {
+            Some(weights) if weight_factor < 1.0 => {
+                Some(weights.into_iter().map(|w| w * weight_factor).collect())
+            }
+            None if weight_factor < 1.0 => Some(vec![
+                weight_factor;
+                (image.width() * image.height()) as usize
+            ]),
+            weights => weights,
+        };
+        let background = if options.ignore_background {
+            image.background_colors()?
+        } else {
+            vec![]
+        };
+        let mut excluded_colors = options.excluded_colors.clone();
+        excluded_colors.extend(&background);
+        // The pixels filtered by the excluded colors only are kept as before the other options
+        let keep_transparent = weights.is_none() && !options.ignore_background;
+        let color_bytes =
+            image.filtered_image_bytes(&excluded_colors, weights.as_deref(), keep_transparent)?;
+        Ok((color_bytes, background))
     }
 
     /// Colors extraction and errors handling
+    /// With a frames selection, the palette of the selected frames
+    /// weighted by their duration.
     pub fn get_colors_from_images(
         &self,
         nb_colors: u8,
-        excluded_colors: &Vec<Color>,
-        bc_color: Option<&Color>,
-    ) -> Result<Vec<Color>, String> {
-        let fv = self
-            .image
-            .as_ref()
-            .unwrap()
-            .filtered_image_bytes(&excluded_colors);
-        if fv.is_err() {
-            return Err(fv.err().to_owned().unwrap());
+        options: &ExtractionOptions,
+    ) -> Result<Palette, String> {
+        if options.svg_native && self.is_svg() {
+            return self.get_svg_native_colors(nb_colors, options);
         }
-        let fv = fv.unwrap();
-        let (color_bytes, color_format) = if !excluded_colors.is_empty() {
-            (fv.as_slice(), ColorFormat::Rgb)
-        } else {
-            (
-                self.image.as_ref().unwrap().as_bytes(),
-                self.image.as_ref().unwrap().color_format().unwrap(),
-            )
+        let Some(selector) = options.frames else {
+            let image = self
+                .image()
+                .as_ref()
+                .map_err(|_| "Error while opening the file!".to_string())?;
+            if options.excluded_colors.is_empty()
+                && options.mask.is_none()
+                && options.weighting.is_none()
+                && !options.ignore_background
+            {
+                // Nothing to filter, the pixels are quantized as decoded
+                return palette_from_bytes(
+                    image.as_bytes(),
+                    image.color_format()?,
+                    nb_colors,
+                    options,
+                    vec![],
+                );
+            }
+            let (color_bytes, background) = self.extraction_bytes(image, options, 1.0)?;
+            return palette_from_bytes(
+                &color_bytes,
+                ColorFormat::Rgb,
+                nb_colors,
+                options,
+                background,
+            );
         };
+        let frames = self.frames(selector)?;
+        let max_duration = frames
+            .iter()
+            .map(|frame| frame.duration_ms)
+            .fold(0.0, f32::max);
+        let mut color_bytes = vec![];
+        let mut background = vec![];
+        for frame in frames {
+            let (frame_bytes, frame_background) =
+                self.extraction_bytes(&frame.image, options, frame.duration_ms / max_duration)?;
+            color_bytes.extend(frame_bytes);
+            for color in frame_background {
+                if !background.contains(&color) {
+                    background.push(color);
+                }
+            }
+        }
+        palette_from_bytes(
+            &color_bytes,
+            ColorFormat::Rgb,
+            nb_colors,
+            options,
+            background,
+        )
+    }
+
+    /// Palettes of the selected frames, with their number and duration
+    pub fn get_frames_colors(
+        &self,
+        nb_colors: u8,
+        options: &ExtractionOptions,
+        selector: FramesSelector,
+    ) -> Result<Vec<(AnimationFrame, Palette)>, String> {
+        let mut frames_palettes = vec![];
+        for frame in self.frames(selector)? {
+            let (color_bytes, background) = self.extraction_bytes(&frame.image, options, 1.0)?;
+            let palette = palette_from_bytes(
+                &color_bytes,
+                ColorFormat::Rgb,
+                nb_colors,
+                options,
+                background,
+            )?;
+            frames_palettes.push((frame, palette));
+        }
+        Ok(frames_palettes)
+    }
+
+    /// Exact colors painted in a SVG file, from the larger painted area
+    fn get_svg_native_colors(
+        &self,
+        nb_colors: u8,
+        options: &ExtractionOptions,
+    ) -> Result<Palette, String> {
+        let painted_colors = self
+            .encoded_data()
+            .map_err(image::ImageError::IoError)
+            .and_then(|svg_data| svg_painted_colors(&svg_data));
+        if painted_colors.is_err() {
+            return Err("Error while parsing the SVG file!".to_string());
+        }
+        let painted_colors = painted_colors
+            .unwrap()
+            .into_iter()
+            .filter(|(color, _)| !is_excluded_color(color, &options.excluded_colors))
+            .take(nb_colors as usize)
+            .collect::<Vec<(Color, f32)>>();
+        if painted_colors.is_empty() {
+            return Err("No painted colors left in the SVG file.".to_string());
+        }
+        let mut colors = painted_colors
+            .iter()
+            .map(|(color, _)| *color)
+            .collect::<Vec<Color>>();
+        sort_by_contrast(&mut colors, options);
+        // The colors proportions are those of their painted areas
+        let painted_area = painted_colors.iter().map(|(_, area)| area).sum::<f32>();
+        let proportions = colors
+            .iter()
+            .map(|color| {
+                let (_, area) = painted_colors.iter().find(|(c, _)| c == color).unwrap();
+                area / painted_area.max(f32::EPSILON)
+            })
+            .collect();
+        Ok(Palette {
+            colors,
+            background: vec![],
+            proportions,
+            pixels: painted_area.round() as u64,
+        })
+    }
 
-        let colors = color_thief::get_palette(color_bytes, color_format, 10, nb_colors as u8);
-        let colors = colors.unwrap();
-        let mut distinct_colors = Vec::<Color>::with_capacity(colors.len());
-        for color in colors {
-            if !distinct_colors.contains(&color) {
-                distinct_colors.push(color);
+    /// Selected frames of an animated image, or the single frame of a still image
+    fn frames(&self, selector: FramesSelector) -> Result<Vec<AnimationFrame>, String> {
+        let decoding_error = || "Error while decoding the image frames!".to_string();
+        if !self.is_svg() {
+            let frames = self
+                .encoded_data()
+                .map_err(image::ImageError::IoError)
+                .and_then(|data| decode_frames(&data, selector));
+            match frames {
+                Ok(Some(frames)) if frames.is_empty() => {
+                    return Err("No frame matches the frames selection.".to_string())
+                }
+                Ok(Some(frames)) => return Ok(frames),
+                Ok(None) => {}
+                Err(_) => return Err(decoding_error()),
             }
         }
-        if let Some(cc) = bc_color {
-            distinct_colors.sort_by(|c1, c2| {
-                c1.contrast_with(*cc)
-                    .partial_cmp(&c2.contrast_with(*cc))
-                    .map(Ordering::reverse)
-                    .unwrap()
-            });
+        match self.image() {
+            Ok(image) => Ok(vec![AnimationFrame::still(image.clone())]),
+            Err(_) => Err(decoding_error()),
+        }
+    }
+}
+
+/// Colors of a decoded image file, or the reason it could not be opened
+pub fn get_image_file_colors(
+    image_file: &ImageFile,
+    nb_colors: u8,
+    options: &ExtractionOptions,
+) -> Result<Palette, String> {
+    if let Some(error_message) = image_file.open_error(options.frames) {
+        return Err(error_message);
+    }
+    // Colors extracting
+    image_file.get_colors_from_images(nb_colors, options)
+}
+
+/// Palette of the RGB or RGBA pixels bytes, with the proportions of its colors
+/// The transparent pixels are ignored, as by the quantizer.
+pub fn palette_from_bytes(
+    color_bytes: &[u8],
+    color_format: ColorFormat,
+    nb_colors: u8,
+    options: &ExtractionOptions,
+    background: Vec<Color>,
+) -> Result<Palette, String> {
+    let colors = quantize(color_bytes, color_format, nb_colors, options)?;
+    let (proportions, pixels) = color_proportions(color_bytes, color_format, &colors);
+    Ok(Palette {
+        colors,
+        background,
+        proportions,
+        pixels,
+    })
+}
+
+/// Distinct colors of the pixels bytes, sorted if requested
+fn quantize(
+    color_bytes: &[u8],
+    color_format: ColorFormat,
+    nb_colors: u8,
+    options: &ExtractionOptions,
+) -> Result<Vec<Color>, String> {
+    let colors =
+        color_thief::get_palette(color_bytes, color_format, QUANTIZATION_QUALITY, nb_colors);
+    if colors.is_err() {
+        return Err("Not enough pixels left to extract colors from.".to_string());
+    }
+    let colors = colors.unwrap();
+    let mut distinct_colors = Vec::<Color>::with_capacity(colors.len());
+    for color in colors {
+        if !distinct_colors.contains(&color) {
+            distinct_colors.push(color);
+        }
+    }
+    sort_by_contrast(&mut distinct_colors, options);
+    Ok(distinct_colors)
+}
+
+/// Shares of the pixels closest to each color, sampled as for the quantization,
+/// and the number of opaque pixels
+fn color_proportions(
+    color_bytes: &[u8],
+    color_format: ColorFormat,
+    colors: &[Color],
+) -> (Vec<f32>, u64) {
+    let is_opaque = |pixel: &&[u8]| color_format != ColorFormat::Rgba || pixel[3] >= MIN_ALPHA;
+    let color_size = if color_format == ColorFormat::Rgba {
+        4
+    } else {
+        3
+    };
+    let mut counts = vec![0_u64; colors.len()];
+    for pixel in color_bytes
+        .chunks_exact(color_size)
+        .step_by(QUANTIZATION_QUALITY as usize)
+        .filter(is_opaque)
+    {
+        let pixel_color = Color::pixels_to_rbg(pixel, color_format);
+        let closest_color = colors
+            .iter()
+            .map(|color| pixel_color.delta_rgb(*color))
+            .enumerate()
+            .min_by(|(_, delta1), (_, delta2)| delta1.total_cmp(delta2));
+        if let Some((index, _)) = closest_color {
+            counts[index] += 1;
         }
-        Ok(distinct_colors)
+    }
+    let nb_pixels = counts.iter().sum::<u64>().max(1) as f32;
+    let proportions = counts
+        .into_iter()
+        .map(|count| count as f32 / nb_pixels)
+        .collect();
+    let nb_opaque_pixels = color_bytes
+        .chunks_exact(color_size)
+        .filter(is_opaque)
+        .count();
+    (proportions, nb_opaque_pixels as u64)
+}
+
+/// Sort the colors by contrast, if requested
+pub(crate) fn sort_by_contrast(colors: &mut [Color], options: &ExtractionOptions) {
+    if let Some(cc) = options.bc_color {
+        colors.sort_by(|c1, c2| {
+            c1.contrast_with(cc)
+                .partial_cmp(&c2.contrast_with(cc))
+                .map(Ordering::reverse)
+                .unwrap()
+        });
     }
 }
@@ -1,28 +1,52 @@
 use crate::{Color, ColorFormat, ColorTrait};
 use image::DynamicImage;
+use std::collections::HashMap;
 
 const MAX_DISTANCE: f32 = 585225.0;
+// Same opacity threshold as the quantizer
+pub const MIN_ALPHA: u8 = 125;
+// Minimal share of the border pixels for a background color
+const MIN_BACKGROUND_SHARE: f32 = 0.2;
+const MAX_BACKGROUND_COLORS: usize = 6;
+// Color difference between neighbouring pixels of the corners flood fill,
+// small enough to stop at the subject edges but to follow the gradients
+const FLOOD_FILL_TOLERANCE: f32 = 0.005;
+// Minimal share of the flood filled pixels for a background color
+const MIN_FILLED_SHARE: f32 = 0.02;
+
+/// Whether a color is less than 5% distant of an excluded color
+pub fn is_excluded_color(color: &Color, excluded_colors: &[Color]) -> bool {
+    excluded_colors.iter().any(|ex_color| {
+        let dist = color.delta_rgb(Color {
+            r: ex_color.r,
+            g: ex_color.g,
+            b: ex_color.b,
+        });
+        // threshold 5%
+        dist / MAX_DISTANCE < 0.05
+    })
+}
 
 pub trait ImageTrait {
     fn color_format(&self) -> Result<ColorFormat, String>;
     fn color_size(&self) -> usize;
-    fn filtered_image_bytes(&self, exc_color: &[Color]) -> Result<Vec<u8>, String>;
+    fn filtered_image_bytes(
+        &self,
+        exc_color: &[Color],
+        weights: Option<&[f32]>,
+        keep_transparent: bool,
+    ) -> Result<Vec<u8>, String>;
+    fn background_colors(&self) -> Result<Vec<Color>, String>;
 }
 
 impl ImageTrait for DynamicImage {
     fn color_format(&self) -> Result<ColorFormat, String> {
         match self.color() {
-            image::ColorType::Rgb8 => {
-                return Ok(ColorFormat::Rgb);
-            }
-            image::ColorType::Rgba8 => {
-                return Ok(ColorFormat::Rgba);
-            }
-            other => {
-                return Err(format!(
-                    "Sorry, images with {other:?} color type pixels are not supported."
-                ));
-            }
+            image::ColorType::Rgb8 => Ok(ColorFormat::Rgb),
+            image::ColorType::Rgba8 => Ok(ColorFormat::Rgba),
+            other => Err(format!(
+                "Sorry, images with {other:?} color type pixels are not supported."
+            )),
         }
     }
 
@@ -33,34 +57,193 @@ impl ImageTrait for DynamicImage {
         }
     }
 
-    fn filtered_image_bytes(&self, excluded_colors: &[Color]) -> Result<Vec<u8>, String> {
+    /// RGB bytes of the kept pixels
+    /// Pixels close to the excluded colors are dropped, and
+    /// weighted pixels are kept proportionally to their weight.
+    /// The transparent pixels are dropped, as by the quantizer, unless kept.
+    fn filtered_image_bytes(
+        &self,
+        excluded_colors: &[Color],
+        weights: Option<&[f32]>,
+        keep_transparent: bool,
+    ) -> Result<Vec<u8>, String> {
         let color_format = self.color_format();
         if color_format.is_err() {
             return Err(color_format.err().unwrap());
         }
+        let color_format = color_format.unwrap();
         let color_size = self.color_size();
         let pixels = self.as_bytes();
         let mut colors_bytes_vec = vec![];
+        // Weights accumulation, a pixel is kept each time it reaches 1
+        let mut weight_acc = 0.0;
         for i in (0..pixels.len()).step_by(color_size) {
-            let pixel_color =
-                Color::pixels_to_rbg(&pixels[i..(i + color_size)], self.color_format().unwrap());
-            let mut keep_pixel = true;
-            for ex_color in excluded_colors {
-                let pixel_dist = pixel_color.delta_rgb(Color {
-                    r: ex_color.r,
-                    g: ex_color.g,
-                    b: ex_color.b,
-                });
-                let pixel_dist_pct = pixel_dist / MAX_DISTANCE;
-                if pixel_dist_pct < 0.05 {
-                    // threshold 5%
-                    keep_pixel = false;
-                }
+            if !keep_transparent && color_format == ColorFormat::Rgba && pixels[i + 3] < MIN_ALPHA {
+                continue;
+            }
+            let pixel_color = Color::pixels_to_rbg(&pixels[i..(i + color_size)], color_format);
+            if is_excluded_color(&pixel_color, excluded_colors) {
+                continue;
             }
-            if keep_pixel {
-                colors_bytes_vec.extend(pixel_color.to_slice());
+            match weights {
+                Some(weights) => {
+                    weight_acc += weights[i / color_size];
+                    while weight_acc >= 1.0 {
+                        colors_bytes_vec.extend(pixel_color.to_slice());
+                        weight_acc -= 1.0;
+                    }
+                }
+                None => colors_bytes_vec.extend(pixel_color.to_slice()),
             }
         }
         Ok(colors_bytes_vec)
     }
+
+    /// Background colors estimated from the border pixels and from the corners
+    /// The border colors are grouped by close values, and the groups covering a large
+    /// enough share of the border are the background. The area flood filled from the
+    /// corners, if it covers enough of the border, is the background too: its colors,
+    /// gradients included, are covered by as few colors as the exclusion tolerance allows.
+    fn background_colors(&self) -> Result<Vec<Color>, String> {
+        let color_format = self.color_format()?;
+        let color_size = self.color_size();
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        if width == 0 || height == 0 {
+            return Ok(vec![]);
+        }
+        let border = (width.min(height) / 50).max(1);
+        let on_border = |x: usize, y: usize| {
+            x < border || y < border || x >= width - border || y >= height - border
+        };
+        let pixels = self.as_bytes();
+        let pixel_color = |x: usize, y: usize| {
+            let i = (y * width + x) * color_size;
+            if color_format == ColorFormat::Rgba && pixels[i + 3] < MIN_ALPHA {
+                return None;
+            }
+            Some(Color::pixels_to_rbg(
+                &pixels[i..(i + color_size)],
+                color_format,
+            ))
+        };
+        let nb_border_pixels =
+            width * height - width.saturating_sub(2 * border) * height.saturating_sub(2 * border);
+        let nb_border_pixels = nb_border_pixels as f32;
+
+        let mut border_groups = ColorGroups::default();
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(color) = pixel_color(x, y).filter(|_| on_border(x, y)) {
+                    border_groups.add(color);
+                }
+            }
+        }
+        let mut background = Vec::<Color>::with_capacity(MAX_BACKGROUND_COLORS);
+        for (count, color) in border_groups.sorted() {
+            if (count as f32 / nb_border_pixels) < MIN_BACKGROUND_SHARE
+                || background.len() == MAX_BACKGROUND_COLORS
+            {
+                break;
+            }
+            background.push(color);
+        }
+
+        // Flood fill from the corners, through close neighbouring pixels
+        let mut filled = vec![false; width * height];
+        let mut stack = vec![];
+        for (x, y) in [
+            (0, 0),
+            (width - 1, 0),
+            (0, height - 1),
+            (width - 1, height - 1),
+        ] {
+            if pixel_color(x, y).is_some() && !filled[y * width + x] {
+                filled[y * width + x] = true;
+                stack.push((x, y));
+            }
+        }
+        let mut filled_groups = ColorGroups::default();
+        let mut nb_filled_border_pixels = 0;
+        while let Some((x, y)) = stack.pop() {
+            let color = pixel_color(x, y).unwrap();
+            filled_groups.add(color);
+            if on_border(x, y) {
+                nb_filled_border_pixels += 1;
+            }
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            for (nx, ny) in neighbours {
+                if nx >= width || ny >= height || filled[ny * width + nx] {
+                    continue;
+                }
+                let Some(neighbour_color) = pixel_color(nx, ny) else {
+                    continue;
+                };
+                if color.delta_rgb(neighbour_color) / MAX_DISTANCE < FLOOD_FILL_TOLERANCE {
+                    filled[ny * width + nx] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+        if (nb_filled_border_pixels as f32 / nb_border_pixels) >= MIN_BACKGROUND_SHARE {
+            let nb_filled_pixels = filled_groups.count as f32;
+            for (count, color) in filled_groups.sorted() {
+                if (count as f32 / nb_filled_pixels) < MIN_FILLED_SHARE
+                    || background.len() == MAX_BACKGROUND_COLORS
+                {
+                    break;
+                }
+                if !is_excluded_color(&color, &background) {
+                    background.push(color);
+                }
+            }
+        }
+        Ok(background)
+    }
+}
+
+/// Pixels grouped by coarse color, with their count and mean color
+#[derive(Default)]
+struct ColorGroups {
+    count: u32,
+    // (pixels count, summed r, g, b) by coarse color
+    groups: HashMap<[u8; 3], (u32, [u32; 3])>,
+}
+
+impl ColorGroups {
+    fn add(&mut self, color: Color) {
+        self.count += 1;
+        let group = self
+            .groups
+            .entry([color.r >> 4, color.g >> 4, color.b >> 4])
+            .or_insert((0, [0; 3]));
+        group.0 += 1;
+        group.1[0] += color.r as u32;
+        group.1[1] += color.g as u32;
+        group.1[2] += color.b as u32;
+    }
+
+    /// Mean colors of the groups, with their pixels count, the largest groups first
+    fn sorted(self) -> Vec<(u32, Color)> {
+        let mut groups = self
+            .groups
+            .into_values()
+            .map(|(count, sums)| {
+                (
+                    count,
+                    Color {
+                        r: (sums[0] / count) as u8,
+                        g: (sums[1] / count) as u8,
+                        b: (sums[2] / count) as u8,
+                    },
+                )
+            })
+            .collect::<Vec<(u32, Color)>>();
+        groups.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+        groups
+    }
 }
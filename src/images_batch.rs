@@ -0,0 +1,255 @@
+use copypasta::ClipboardContext;
+use crossterm::{
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+};
+use ratatui::{prelude::CrosstermBackend, Terminal};
+use rayon::prelude::*;
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    io::{self, stdout, BufRead},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::cli_ui::{run_app, App, BrowseOptions, GaugeApp, GaugeAppGuard};
+use crate::cluster::ImagesGroup;
+use crate::image_file_lib::get_image_file_colors;
+use crate::palette_cache::CacheKey;
+use crate::remote::{download_file, is_remote_url};
+use crate::uri::{decode_data_uri, is_data_uri, path_label};
+use crate::watch::ImagesWatcher;
+use crate::{ExtractionOptions, ImageFile, Palette};
+
+/// Images colors, sorted alphabetically by path
+pub type ImagesColors = BTreeMap<String, Result<Palette, String>>;
+
+/// Extract the colors of local or remote images in parallel,
+/// with a waiting gauge in the terminal
+pub fn extract_images_colors(
+    images_paths: Vec<String>,
+    nb_colors: u8,
+    options: &ExtractionOptions,
+    with_gauge: bool,
+) -> ImagesColors {
+    let images_colors_map = Arc::new(Mutex::new(ImagesColors::new()));
+    let terminal = with_gauge.then(|| Arc::new(Mutex::new(ratatui::init())));
+    let gauge_app = GaugeApp::default();
+    let gauge = GaugeAppGuard::new(gauge_app);
+    let inc: Arc<Mutex<u16>> = Arc::new(Mutex::new(0));
+    let nb_images = images_paths.len() as u16;
+    // Start the waiting gauge
+    gauge.start();
+    images_paths
+        .into_par_iter()
+        .map(|path| {
+            (
+                path.to_owned(),
+                get_extracted_colors(path, nb_colors, options),
+            )
+        })
+        .for_each(|(path, colors_result)| {
+            let mut shared = images_colors_map.lock().unwrap();
+            let path_copy = path.clone();
+            shared.entry(path).or_insert(colors_result);
+            let Some(terminal) = &terminal else {
+                return;
+            };
+            terminal
+                .lock()
+                .unwrap()
+                .draw(|frame| frame.render_widget(&gauge, frame.area()))
+                .unwrap();
+
+            gauge.handle_events().unwrap();
+            let mut val = inc.lock().unwrap();
+            let prop_val = (((*val) as f32 / nb_images as f32) * 100.0) as u16;
+            gauge.update(prop_val, path_label(&path_copy).into_owned());
+            *val += 1;
+        });
+
+    if with_gauge {
+        ratatui::restore();
+    }
+    Arc::try_unwrap(images_colors_map)
+        .expect("There are still other references to the Arc")
+        .into_inner()
+        .expect("Mutex cannot be locked")
+}
+
+/// Extract again the changed images of a watched collection,
+/// and drop the removed ones
+pub fn update_images_colors(
+    images_colors_map: &mut ImagesColors,
+    changed_paths: Vec<String>,
+    watcher: &ImagesWatcher,
+    nb_colors: u8,
+    options: &ExtractionOptions,
+) {
+    let (existing_paths, removed_paths): (Vec<String>, Vec<String>) = changed_paths
+        .into_iter()
+        .partition(|path| Path::new(path).is_file());
+    for path in removed_paths {
+        images_colors_map.remove(&path);
+    }
+    let updated_colors = existing_paths
+        .into_iter()
+        .filter(|path| images_colors_map.contains_key(path) || watcher.is_new_image(path))
+        .collect::<Vec<String>>()
+        .into_par_iter()
+        .map(|path| {
+            let colors_result = get_extracted_colors(path.clone(), nb_colors, options);
+            (path, colors_result)
+        })
+        .collect::<Vec<_>>();
+    images_colors_map.extend(updated_colors);
+}
+
+/// Browse the images colors in the TUI, with the optional watching, aggregate palette and ranking
+/// The images paths are displayed relatively to the base directory.
+pub fn browse_images_colors(
+    images_colors_map: ImagesColors,
+    base_dir: &str,
+    nb_colors: u8,
+    with_rgb: bool,
+    options: &ExtractionOptions,
+    browse_options: BrowseOptions,
+) -> io::Result<()> {
+    // Headless machines have no clipboard
+    let mut ctx = ClipboardContext::new().ok();
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let tick_rate = Duration::from_millis(250);
+    let app = App::new(
+        images_colors_map,
+        base_dir,
+        nb_colors,
+        with_rgb,
+        options,
+        browse_options,
+    );
+    let res = run_app(&mut terminal, app, tick_rate, &mut ctx);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen,)?;
+
+    terminal.show_cursor()?;
+
+    if let Err(err) = res {
+        println!("{err:?}");
+    }
+    ratatui::restore();
+    Ok(())
+}
+
+/// Images colors as a JSON array
+pub fn images_colors_json(images_colors_map: &ImagesColors) -> Value {
+    images_colors_map
+        .iter()
+        .map(|(path, colors_result)| {
+            let mut image_json = match colors_result {
+                Ok(palette) => palette.to_json(),
+                Err(error_message) => serde_json::json!({ "error": error_message }),
+            };
+            image_json["path"] = Value::from(path_label(path));
+            image_json
+        })
+        .collect()
+}
+
+/// Ranked images colors as a JSON array, with their distance
+pub fn ranked_images_json(images_colors_map: &ImagesColors, ranking: &[(String, f32)]) -> Value {
+    ranking
+        .iter()
+        .filter_map(|(path, distance)| {
+            let mut image_json = images_colors_map.get(path)?.as_ref().ok()?.to_json();
+            image_json["path"] = Value::from(path_label(path));
+            image_json["distance"] = Value::from((*distance as f64 * 100.0).round() / 100.0);
+            Some(image_json)
+        })
+        .collect()
+}
+
+/// Groups of images as a JSON array, with their representative palette and their images
+pub fn images_groups_json(images_groups: &[ImagesGroup]) -> Value {
+    images_groups
+        .iter()
+        .map(|group| {
+            let mut group_json = match &group.palette {
+                Ok(palette) => palette.to_json(),
+                Err(error_message) => serde_json::json!({ "error": error_message }),
+            };
+            group_json["images"] = group
+                .paths
+                .iter()
+                .map(|path| Value::from(path_label(path)))
+                .collect();
+            group_json
+        })
+        .collect()
+}
+
+/// Images paths or URLs of a list, one by line
+/// Empty lines and lines starting with # are skipped.
+pub fn read_images_list<R: BufRead>(reader: R) -> io::Result<Vec<String>> {
+    let mut images_paths = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        images_paths.push(line.to_string());
+    }
+    Ok(images_paths)
+}
+
+/// Extract colors from a local file path, a remote image URL or a data URI
+/// used for iterated extractions in batch cases
+pub fn get_extracted_colors(
+    file_path: String,
+    nb_colors: u8,
+    options: &ExtractionOptions,
+) -> Result<Palette, String> {
+    let remote_file = if is_remote_url(&file_path) {
+        Some(download_file(&file_path, &options.download).map_err(|error| error.to_string())?)
+    } else {
+        None
+    };
+    // Cached palettes skip the decoding
+    let palette_cache = options.palette_cache.as_ref();
+    let cache_key = palette_cache.and_then(|_| match &remote_file {
+        Some(remote_file) => Some(CacheKey::content(&remote_file.data)),
+        None if is_data_uri(&file_path) => Some(CacheKey::content(file_path.as_bytes())),
+        None => CacheKey::local_file(&file_path),
+    });
+    let options_key = options.cache_key(nb_colors, &file_path);
+    if let (Some(palette_cache), Some(cache_key)) = (palette_cache, &cache_key) {
+        if let Some(palette) = palette_cache.get(cache_key, &options_key) {
+            return Ok(palette);
+        }
+    }
+    let image_file = match remote_file {
+        Some(remote_file) => remote_file.into_image_file(options.svg_size)?,
+        None if is_data_uri(&file_path) => {
+            let (image_data, media_type) = decode_data_uri(&file_path)?;
+            ImageFile::from_data(
+                path_label(&file_path).into_owned(),
+                image_data,
+                media_type.as_deref(),
+                options.svg_size,
+            )
+        }
+        None => ImageFile::new(file_path, options.svg_size),
+    };
+    let palette = get_image_file_colors(&image_file, nb_colors, options)?;
+    if let (Some(palette_cache), Some(cache_key)) = (palette_cache, &cache_key) {
+        // A failed caching does not fail the extraction
+        let _ = palette_cache.put(cache_key, &options_key, &palette);
+    }
+    Ok(palette)
+}
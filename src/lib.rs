@@ -0,0 +1,86 @@
+//! Dominant colors extraction
+//! The extraction core (decoding, quantization, colors conversions and exports)
+//! builds without the default native feature, for WebAssembly.
+
+use color_thief::{Color, ColorFormat};
+
+pub mod color_trait;
+pub use color_trait::ColorTrait;
+
+pub mod image_trait;
+use image_trait::ImageTrait;
+
+pub mod image_file_lib;
+pub use image_file_lib::ImageFile;
+
+pub mod mask;
+
+pub mod extraction_options;
+pub use extraction_options::ExtractionOptions;
+
+pub mod palette;
+pub use palette::Palette;
+
+pub mod weighting;
+
+pub mod frames;
+
+pub mod svg;
+
+pub mod export;
+
+#[cfg(feature = "native")]
+pub mod colors_canvas;
+#[cfg(feature = "native")]
+use colors_canvas::ColorsCanvas;
+
+#[cfg(feature = "native")]
+pub mod cli_ui;
+
+#[cfg(feature = "native")]
+pub mod images_batch;
+
+#[cfg(feature = "native")]
+pub mod remote;
+
+#[cfg(feature = "native")]
+pub mod netrc;
+
+#[cfg(feature = "native")]
+pub mod page;
+
+#[cfg(feature = "native")]
+pub mod uri;
+
+#[cfg(feature = "native")]
+pub mod palette_cache;
+
+#[cfg(feature = "native")]
+pub mod http_cache;
+
+#[cfg(feature = "native")]
+pub mod watch;
+
+#[cfg(feature = "native")]
+pub mod aggregate;
+
+#[cfg(feature = "native")]
+pub mod near;
+
+#[cfg(feature = "native")]
+pub mod cluster;
+
+#[cfg(feature = "native")]
+pub mod palette_index;
+
+#[cfg(feature = "native")]
+pub mod server;
+
+#[cfg(feature = "native")]
+pub mod rpc;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub const IMAGES_EXTENSION_REGEX_PATTERN: &str =
+    r"(?i)\.(png|jpe?g|gif|bmp|ico|tiff|webp|avif|pnm|dds|tga|svgz?)";
@@ -1,48 +1,58 @@
-use clap::{Arg, ArgAction, Command};
-use color_thief::{Color, ColorFormat};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use color_thief::Color;
+use crossterm::{
+    cursor::MoveTo,
+    terminal::{Clear, ClearType},
+    ExecutableCommand,
+};
 use regex::Regex;
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::HashSet,
     fs::{self, File},
-    io::{self, stdout},
-    path::Path,
-    process,
-    sync::{Arc, Mutex},
+    io::{self, IsTerminal, Read},
+    path::{Path, PathBuf},
+    process, thread,
+    time::Duration,
 };
 
-use url::Url;
-
-use copypasta::ClipboardContext;
-use crossterm::{
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
+use copycolors::{
+    aggregate::{aggregate_palette, ImagesWeights},
+    cli_ui::BrowseOptions,
+    cluster::{cluster_images, ClusterCriterion},
+    colors_canvas::ColorsCanvas,
+    extraction_options::parse_svg_size,
+    frames::FramesSelector,
+    http_cache::HttpCache,
+    images_batch::{
+        browse_images_colors, extract_images_colors, get_extracted_colors, images_colors_json,
+        images_groups_json, ranked_images_json, read_images_list, update_images_colors,
+        ImagesColors,
+    },
+    mask::Mask,
+    near::{read_palette_file, NearQuery},
+    netrc::Netrc,
+    page::download_page_images_urls,
+    palette_cache::{prune_cache, PaletteCache, DEFAULT_PALETTE_CACHE_SIZE},
+    palette_index::{IndexQuery, PaletteIndex},
+    remote::{
+        download_file, format_size, is_remote_url, parse_header, parse_size, DownloadOptions,
+        DEFAULT_CONNECT_TIMEOUT_S, DEFAULT_MAX_DOWNLOAD_SIZE, DEFAULT_READ_TIMEOUT_S,
+        DEFAULT_RETRIES, MAX_RETRIES,
+    },
+    rpc::run_rpc,
+    server::{serve, ServerOptions},
+    uri::{decode_data_uri, file_uri_path, is_data_uri, path_label},
+    watch::ImagesWatcher,
+    weighting::Weighting,
+    ColorTrait, ExtractionOptions, ImageFile, Palette, IMAGES_EXTENSION_REGEX_PATTERN,
 };
-use ratatui::{prelude::CrosstermBackend, Terminal};
-use std::time::Duration;
-
-mod colors_canvas;
-use colors_canvas::ColorsCanvas;
-
-mod color_trait;
-use color_trait::ColorTrait;
-
-mod image_trait;
-use image_trait::ImageTrait;
-
-mod image_file_lib;
-use image_file_lib::ImageFile;
-
-mod cli_ui;
-use cli_ui::{run_app, App, GaugeApp, GaugeAppGuard};
 
 use io::ErrorKind;
 use walkdir::WalkDir;
 
-use rayon::prelude::*;
-
-const IMAGES_EXTENSION_REGEX_PATTERN: &str =
-    r"(?i)\.(png|jpe?g|gif|bmp|ico|tiff|webp|avif|pnm|dds|tga)";
+// Path reading the image from the standard input
+const STDIN_PATH: &str = "-";
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 fn main() -> io::Result<()> {
     /*
@@ -55,8 +65,111 @@ fn main() -> io::Result<()> {
         .arg(
             Arg::new("file_path")
                 .value_name("DIR_OR_FILE_PATH")
-                .required(true)
-                .help("Local repertory / Local or remote image path"),
+                .num_args(1..)
+                .required_unless_present_any(["from-list", "stdin-paths", "page"])
+                .help(
+                    r"Local repertories / Local or remote images paths / Glob patterns
+- for the standard input",
+                ),
+        )
+        .arg(
+            Arg::new("from-list")
+                .long("from-list")
+                .value_name("LIST_PATH")
+                .help("File listing local or remote images paths, one by line"),
+        )
+        .arg(
+            Arg::new("stdin-paths")
+                .long("stdin-paths")
+                .help("Read local or remote images paths from the standard input, one by line")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("page")
+                .long("page")
+                .value_name("PAGE_URL")
+                .action(ArgAction::Append)
+                .help(
+                    "Browse the images of a web page (img, srcset, og:image and CSS backgrounds)",
+                ),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print the extracted colors as JSON")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("aggregate")
+                .long("aggregate")
+                .help("Extract also the palette of all the images together")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("aggregate-file")
+                .long("aggregate-file")
+                .value_name("FILE")
+                .requires("aggregate")
+                .help("Write the aggregate palette as JSON to that file, as needed with --json"),
+        )
+        .arg(
+            Arg::new("equal-weights")
+                .long("equal-weights")
+                .requires("aggregate")
+                .help("Weigh each image equally in the aggregate palette, whatever its resolution")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("near")
+                .long("near")
+                .value_name("COLOURS")
+                .num_args(1..)
+                .help("Rank the images by the distance of their palette to these hexadecimal colors")
+        )
+        .arg(
+            Arg::new("near-palette")
+                .long("near-palette")
+                .value_name("PALETTE_PATH")
+                .num_args(1)
+                .conflicts_with("near")
+                .help("Rank the images by the distance of their palette to a GIMP palette (.gpl)")
+        )
+        .arg(
+            Arg::new("top")
+                .long("top")
+                .value_name("NB_IMAGES")
+                .num_args(1)
+                .default_value("10")
+                .help("Number of ranked images to keep"),
+        )
+        .arg(
+            Arg::new("clusters")
+                .long("clusters")
+                .value_name("NB_GROUPS")
+                .num_args(1)
+                .conflicts_with_all(["near", "near-palette"])
+                .help("Group the images with similar palettes into this number of groups"),
+        )
+        .arg(
+            Arg::new("cluster-threshold")
+                .long("cluster-threshold")
+                .value_name("DELTA_E")
+                .num_args(1)
+                .conflicts_with_all(["clusters", "near", "near-palette"])
+                .help("Group the images with similar palettes, up to this average distance"),
+        )
+        .arg(
+            Arg::new("no-tui")
+                .long("no-tui")
+                .help("Print the colors of the images instead of browsing them (default when the output is not a terminal)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .conflicts_with("json")
+                .help("Extract again the images when they are added, modified or removed")
+                .action(ArgAction::SetTrue),
         )
         .arg(
             Arg::new("nb-colors")
@@ -120,13 +233,384 @@ When bcw & bcb are  both requested, bcb is used.",
                 )
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("ignore-background")
+                .long("ignore-background")
+                .help(
+                    r"Detect the background colors from the image border,
+exclude them from the extraction and report them separately.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("weighting")
+                .long("weighting")
+                .short('w')
+                .value_name("MODE")
+                .next_line_help(true)
+                .help(
+                    r"Pixels weighting mode for the extraction:
+center: pixels near the image center count more.
+saliency: pixels in high contrast regions count more.
+saturation: pixels of saturated colors count more.",
+                ),
+        )
+        .arg(
+            Arg::new("frames")
+                .long("frames")
+                .value_name("SELECTION")
+                .next_line_help(true)
+                .help(
+                    r"Frames of animated GIF, APNG and WebP images to extract:
+all, N (the frame N only) or every:K (one frame every K frames).
+One palette is extracted by frame.",
+                ),
+        )
+        .arg(
+            Arg::new("aggregate-frames")
+                .long("aggregate-frames")
+                .help(
+                    r"Extract a single palette of the selected frames,
+weighted by the frames duration (always used for repertories).",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("svg-size")
+                .long("svg-size")
+                .value_name("PIXELS")
+                .num_args(1)
+                .default_value("512")
+                .help("Size of the larger side of the rasterized SVG images, up to 8192"),
+        )
+        .arg(
+            Arg::new("svg-native")
+                .long("svg-native")
+                .help(
+                    r"Extract the exact fill, stroke and gradient colors of SVG images,
+ordered by their approximate painted area.",
+                )
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["mask", "ignore-background", "weighting"]),
+        )
+        .arg(
+            Arg::new("connect-timeout")
+                .long("connect-timeout")
+                .value_name("SECONDS")
+                .num_args(1)
+                .help(format!(
+                    "Connection timeout of the remote images downloads [default: {DEFAULT_CONNECT_TIMEOUT_S}]"
+                )),
+        )
+        .arg(
+            Arg::new("read-timeout")
+                .long("read-timeout")
+                .value_name("SECONDS")
+                .num_args(1)
+                .help(format!(
+                    "Read timeout of the remote images downloads [default: {DEFAULT_READ_TIMEOUT_S}]"
+                )),
+        )
+        .arg(
+            Arg::new("max-download-size")
+                .long("max-download-size")
+                .value_name("SIZE")
+                .num_args(1)
+                .help(format!(
+                    "Maximum size of a remote image (Ex: 500K, 20M, 1G) [default: {}]",
+                    format_size(DEFAULT_MAX_DOWNLOAD_SIZE)
+                )),
+        )
+        .arg(
+            Arg::new("retries")
+                .long("retries")
+                .num_args(1)
+                .help(format!(
+                    "Retries of the remote images downloads after a server error or a timeout, up to {MAX_RETRIES} [default: {DEFAULT_RETRIES}]"
+                )),
+        )
+        .arg(
+            Arg::new("header")
+                .long("header")
+                .short('H')
+                .value_name("HEADER")
+                .action(ArgAction::Append)
+                .next_line_help(true)
+                .help(
+                    r"Header sent with the remote images requests, can be repeated.
+The ${VARIABLE} in the value are read from the environment.
+Ex: -H 'Authorization: Bearer ${ASSETS_TOKEN}'",
+                ),
+        )
+        .arg(
+            Arg::new("user-agent")
+                .long("user-agent")
+                .num_args(1)
+                .help("User agent of the remote images requests"),
+        )
+        .arg(
+            Arg::new("netrc")
+                .long("netrc")
+                .help("Authenticate the remote images requests with the netrc file logins")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("http-cache")
+                .long("http-cache")
+                .value_name("CACHE_DIR")
+                .help("Cache the remote images in that directory, revalidated with the servers"),
+        )
+        .arg(
+            Arg::new("http-cache-size")
+                .long("http-cache-size")
+                .value_name("SIZE")
+                .num_args(1)
+                .default_value("500M")
+                .help(
+                    "Maximum size of the remote images cache, the least recently used are evicted",
+                ),
+        )
+        .arg(
+            Arg::new("offline")
+                .long("offline")
+                .requires("http-cache")
+                .help("Serve the remote images from the cache only, without requests")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("mask")
+                .value_name("MASK_PATH")
+                .long("mask")
+                .short('m')
+                .next_line_help(true)
+                .help(
+                    r"Mask image (or repertory of masks named like the images).
+Only the pixels where the mask is above the threshold are extracted.
+The mask is scaled to the image size.",
+                ),
+        )
+        .arg(
+            Arg::new("mask-threshold")
+                .long("mask-threshold")
+                .num_args(1)
+                .default_value("0")
+                .help("Mask gray level (0-255) above which pixels are kept"),
+        )
+        .arg(
+            Arg::new("mask-weights")
+                .long("mask-weights")
+                .help("Use the mask gray levels as pixels weights")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-cache")
+                .long("no-cache")
+                .help("Do not use the extracted palettes cache")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("refresh")
+                .long("refresh")
+                .conflicts_with("no-cache")
+                .help("Extract again the cached palettes and replace them")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("cache-size")
+                .long("cache-size")
+                .value_name("SIZE")
+                .num_args(1)
+                .conflicts_with("no-cache")
+                .help(format!(
+                    "Maximum size of the extracted palettes cache, the least recently used are evicted [default: {}]",
+                    format_size(DEFAULT_PALETTE_CACHE_SIZE)
+                )),
+        )
+        .subcommand(
+            Command::new("cache")
+                .about("Manage the extracted palettes cache")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("prune")
+                        .about(
+                            "Remove the cached palettes unused for a while, or of modified or deleted files",
+                        )
+                        .arg(
+                            Arg::new("older-than")
+                                .long("older-than")
+                                .value_name("DAYS")
+                                .num_args(1)
+                                .default_value("30")
+                                .help("Age of the unused palettes to remove"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("index")
+                .about("Store the palettes of the images of repertories in a searchable index, updated incrementally")
+                .arg(
+                    Arg::new("repertories")
+                        .value_name("DIR_PATH")
+                        .num_args(1..)
+                        .required(true)
+                        .help("Repertories of images to index, recursively"),
+                )
+                .arg(index_path_arg())
+                .arg(
+                    Arg::new("nb-colors")
+                        .long("nb-colors")
+                        .short('n')
+                        .num_args(1)
+                        .default_value("5")
+                        .help("Specify the number of colors to extract"),
+                ),
+        )
+        .subcommand(
+            Command::new("query")
+                .about("Search the indexed images by colors, hue, colorfulness or file name")
+                .arg(index_path_arg())
+                .arg(
+                    Arg::new("near")
+                        .long("near")
+                        .value_name("COLOURS")
+                        .num_args(1..)
+                        .help("Rank the images by the distance of their palette to these hexadecimal colors"),
+                )
+                .arg(
+                    Arg::new("near-palette")
+                        .long("near-palette")
+                        .value_name("PALETTE_PATH")
+                        .num_args(1)
+                        .conflicts_with("near")
+                        .help("Rank the images by the distance of their palette to a GIMP palette (.gpl)"),
+                )
+                .arg(
+                    Arg::new("hue")
+                        .long("hue")
+                        .value_name("MIN-MAX")
+                        .num_args(1)
+                        .help("Keep the images with a color in this hue range, in degrees (e.g. 200-250, or 340-20)"),
+                )
+                .arg(
+                    Arg::new("min-share")
+                        .long("min-share")
+                        .value_name("PROPORTION")
+                        .num_args(1)
+                        .default_value("0.1")
+                        .requires("hue")
+                        .help("Minimum proportion of the palette in the hue range"),
+                )
+                .arg(
+                    Arg::new("min-colorfulness")
+                        .long("min-colorfulness")
+                        .value_name("COLORFULNESS")
+                        .num_args(1)
+                        .help("Keep the images at least this colorful (0 for grayscale, 100 for vivid)"),
+                )
+                .arg(
+                    Arg::new("max-colorfulness")
+                        .long("max-colorfulness")
+                        .value_name("COLORFULNESS")
+                        .num_args(1)
+                        .help("Keep the images at most this colorful"),
+                )
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .value_name("PATTERN")
+                        .num_args(1)
+                        .help("Keep the images with a file name matching this glob pattern, case insensitive"),
+                )
+                .arg(
+                    Arg::new("top")
+                        .long("top")
+                        .value_name("NB_IMAGES")
+                        .num_args(1)
+                        .default_value("10")
+                        .help("Number of images to keep"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the matching images as JSON")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("rgb")
+                        .long("rgb")
+                        .short('r')
+                        .help("Print RGB code")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("canvas")
+                        .short('c')
+                        .long("canvas")
+                        .help("Show colors canvas")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("serve")
+                .about("Serve the colors extraction over HTTP: POST /extract and GET /health")
+                .arg(
+                    Arg::new("port")
+                        .long("port")
+                        .short('p')
+                        .num_args(1)
+                        .default_value("8080")
+                        .help("Port to listen on"),
+                )
+                .arg(
+                    Arg::new("host")
+                        .long("host")
+                        .num_args(1)
+                        .default_value("127.0.0.1")
+                        .help("Address to listen on, 0.0.0.0 for all the interfaces"),
+                )
+                .arg(
+                    Arg::new("workers")
+                        .long("workers")
+                        .value_name("NB_WORKERS")
+                        .num_args(1)
+                        .help("Number of extractions run at the same time, the number of CPUs by default"),
+                )
+                .arg(
+                    Arg::new("max-upload-size")
+                        .long("max-upload-size")
+                        .value_name("SIZE")
+                        .num_args(1)
+                        .default_value("20M")
+                        .help("Maximum size of the uploaded images"),
+                )
+                .arg(
+                    Arg::new("allow-private-urls")
+                        .long("allow-private-urls")
+                        .action(ArgAction::SetTrue)
+                        .help("Extract the image URLs of private, loopback or link-local hosts"),
+                ),
+        )
+        .subcommand(Command::new("rpc").about(
+            "Answer line-delimited JSON-RPC requests on the standard input: extract, contrast, convert and export",
+        ))
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
         .get_matches();
 
+    match matches.subcommand() {
+        Some(("cache", cache_matches)) => return run_cache_command(cache_matches),
+        Some(("index", index_matches)) => return run_index_command(index_matches),
+        Some(("query", query_matches)) => return run_query_command(query_matches),
+        Some(("serve", serve_matches)) => return run_serve_command(serve_matches),
+        Some(("rpc", _)) => return run_rpc(default_options()),
+        _ => {}
+    }
+
     let show_canvas = matches.get_flag("canvas");
     let with_rgb = matches.get_flag("rgb");
     let excluded_colors = if let Some(ec) = matches.get_many::<String>("exc-colors") {
         ec.map(|hex| hex.as_str())
-            .map(|hex| Color::hex_to_rgb(hex).unwrap())
+            .map(parse_hex_code)
             .collect::<Vec<Color>>()
     } else {
         vec![]
@@ -135,9 +619,9 @@ When bcw & bcb are  both requested, bcb is used.",
     let bcw = matches.get_flag("bcw");
     let bcb = matches.get_flag("bcb");
     let bc_color = if bcb {
-        Some(&Color { r: 0, g: 0, b: 0 })
+        Some(Color { r: 0, g: 0, b: 0 })
     } else if bcw {
-        Some(&Color {
+        Some(Color {
             r: 255,
             g: 255,
             b: 255,
@@ -146,13 +630,71 @@ When bcw & bcb are  both requested, bcb is used.",
         None
     };
 
-    let mut file_path = match matches.get_raw("file_path") {
-        Some(f) => String::from(f.into_iter().next().unwrap().to_str().unwrap()),
-        None => {
-            eprintln!("You should put a directory path or a local or remote file path.");
+    let mask = match matches.get_one::<String>("mask") {
+        Some(mask_path) => {
+            let threshold = match matches
+                .get_one::<String>("mask-threshold")
+                .unwrap()
+                .parse::<u8>()
+            {
+                Ok(threshold) => threshold,
+                Err(_) => {
+                    eprintln!("The mask threshold should be a gray level between 0 and 255.");
+                    process::exit(1);
+                }
+            };
+            if !Path::new(mask_path).exists() {
+                eprintln!(
+                    "Sorry, the mask: \"{mask_path}\" is not found. Please, make sure it exists."
+                );
+                process::exit(1);
+            }
+            match Mask::open(
+                mask_path.to_string(),
+                threshold,
+                matches.get_flag("mask-weights"),
+            ) {
+                Ok(mask) => Some(mask),
+                Err(error_message) => {
+                    eprintln!("{}", error_message);
+                    process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let weighting = match matches.get_one::<String>("weighting") {
+        Some(mode) => match Weighting::from_name(mode) {
+            Ok(weighting) => Some(weighting),
+            Err(error_message) => {
+                eprintln!("{}", error_message);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let frames = match matches.get_one::<String>("frames") {
+        Some(selection) => match FramesSelector::from_name(selection) {
+            Ok(selector) => Some(selector),
+            Err(error_message) => {
+                eprintln!("{}", error_message);
+                process::exit(1);
+            }
+        },
+        None if matches.get_flag("aggregate-frames") => Some(FramesSelector::All),
+        None => None,
+    };
+
+    let svg_size = match parse_svg_size(matches.get_one::<String>("svg-size").unwrap()) {
+        Ok(size) => size,
+        Err(error_message) => {
+            eprintln!("{}", error_message);
             process::exit(1);
         }
     };
+
     let nb_colors = match matches.get_raw("nb-colors") {
         Some(nb) => match nb
             .into_iter()
@@ -180,178 +722,961 @@ When bcw & bcb are  both requested, bcb is used.",
         }
     };
 
-    let repertory = fs::read_dir(&file_path);
-    if repertory.is_ok() {
-        /*
-        Directory Case
-        */
-        let mut ctx = ClipboardContext::new().unwrap();
-        let regex = matches.get_raw("regex");
-        let regex = match regex {
-            Some(r) => String::from(r.into_iter().next().unwrap().to_str().unwrap()),
-            None => "".to_string(),
+    // The unset download settings keep their default values
+    let default_download = DownloadOptions::default();
+    let timeout = |arg_name: &str, default: Duration| {
+        let Some(seconds) = matches.get_one::<String>(arg_name) else {
+            return default;
         };
-        let recursive = matches.get_flag("recursive");
-        let matching_files = get_matching_files(&file_path, &regex, recursive);
-        if let Err(error_message) = matching_files {
+        match seconds.parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Duration::from_secs(seconds),
+            _ => {
+                eprintln!("The --{arg_name} should be a positive number of seconds.");
+                process::exit(1);
+            }
+        }
+    };
+    let max_download_size = match matches
+        .get_one::<String>("max-download-size")
+        .map(|size| parse_size(size))
+    {
+        None => default_download.max_size,
+        Some(Ok(size)) => size,
+        Some(Err(error_message)) => {
             eprintln!("{}", error_message);
             process::exit(1);
         }
+    };
+    let retries = match matches
+        .get_one::<String>("retries")
+        .map(|retries| retries.parse::<u32>())
+    {
+        None => default_download.retries,
+        Some(Ok(retries)) if retries <= MAX_RETRIES => retries,
+        _ => {
+            eprintln!("The number of retries should be a number between 0 and {MAX_RETRIES}.");
+            process::exit(1);
+        }
+    };
+    let mut headers = vec![];
+    for header in matches.get_many::<String>("header").unwrap_or_default() {
+        match parse_header(header) {
+            Ok(header) => headers.push(header),
+            Err(error_message) => {
+                eprintln!("{}", error_message);
+                process::exit(1);
+            }
+        }
+    }
+    let cache = match matches.get_one::<String>("http-cache") {
+        Some(cache_dir) => {
+            let cache_size = match parse_size(matches.get_one::<String>("http-cache-size").unwrap())
+            {
+                Ok(size) => size,
+                Err(error_message) => {
+                    eprintln!("{}", error_message);
+                    process::exit(1);
+                }
+            };
+            match HttpCache::new(PathBuf::from(cache_dir), cache_size) {
+                Ok(cache) => Some(cache),
+                Err(_) => {
+                    eprintln!("Sorry, the cache directory: \"{cache_dir}\" could not be created.");
+                    process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+    let download = DownloadOptions {
+        connect_timeout: timeout("connect-timeout", default_download.connect_timeout),
+        read_timeout: timeout("read-timeout", default_download.read_timeout),
+        max_size: max_download_size,
+        retries,
+        headers,
+        user_agent: matches
+            .get_one::<String>("user-agent")
+            .cloned()
+            .unwrap_or(default_download.user_agent),
+        netrc: matches.get_flag("netrc").then(Netrc::load),
+        cache,
+        offline: matches.get_flag("offline"),
+        ..default_download
+    };
 
-        let images_paths = matching_files.unwrap();
-        let images_colors_map = Arc::new(Mutex::new(
-            HashMap::<String, Result<Vec<Color>, String>>::with_capacity(images_paths.len()),
-        ));
-        let terminal = Arc::new(Mutex::new(ratatui::init()));
-        let gauge_app = GaugeApp::default();
-        let gauge = GaugeAppGuard::new(gauge_app);
-        let inc: Arc<Mutex<u16>> = Arc::new(Mutex::new(0));
-        let nb_images = images_paths.len() as u16;
-        // Start the waiting gauge
-        gauge.start();
-        images_paths
-            .into_par_iter()
-            .map(|path| {
-                (
-                    path.to_owned(),
-                    get_extracted_colors(path, nb_colors as u8, &excluded_colors, bc_color),
-                )
-            })
-            .for_each(|(path, colors_result)| {
-                let mut shared = images_colors_map.lock().unwrap();
-                let path_copy = path.clone();
-                shared.entry(path).or_insert(colors_result);
-                terminal
-                    .lock()
-                    .unwrap()
-                    .draw(|frame| frame.render_widget(&gauge, frame.area()))
-                    .unwrap();
-
-                gauge.handle_events().unwrap();
-                let mut val = inc.lock().unwrap();
-                let prop_val = (((*val) as f32 / nb_images as f32) * 100.0) as u16;
-                gauge.update(prop_val, path_copy);
-                *val += 1;
-            });
+    // Palettes are cached in the user cache directory
+    let palette_cache_size = match matches
+        .get_one::<String>("cache-size")
+        .map(|size| parse_size(size))
+    {
+        None => DEFAULT_PALETTE_CACHE_SIZE,
+        Some(Ok(size)) => size,
+        Some(Err(error_message)) => {
+            eprintln!("{}", error_message);
+            process::exit(1);
+        }
+    };
+    let palette_cache = if matches.get_flag("no-cache") {
+        None
+    } else {
+        PaletteCache::default_dir().and_then(|dir| {
+            PaletteCache::new(dir, matches.get_flag("refresh"), palette_cache_size).ok()
+        })
+    };
+
+    let options = ExtractionOptions {
+        excluded_colors,
+        bc_color,
+        mask,
+        ignore_background: matches.get_flag("ignore-background"),
+        weighting,
+        frames,
+        svg_size,
+        svg_native: matches.get_flag("svg-native"),
+        download,
+        palette_cache,
+    };
+
+    let as_json = matches.get_flag("json");
+    let regex = match matches.get_one::<String>("regex") {
+        Some(r) => r.to_string(),
+        None => "".to_string(),
+    };
+    let recursive = matches.get_flag("recursive");
+    let watch = matches.get_flag("watch");
+    let near_colors = if let Some(palette_path) = matches.get_one::<String>("near-palette") {
+        match read_palette_file(palette_path) {
+            Ok(colors) => Some(colors),
+            Err(error_message) => {
+                eprintln!("{}", error_message);
+                process::exit(1);
+            }
+        }
+    } else {
+        matches
+            .get_many::<String>("near")
+            .map(|hex_codes| hex_codes.map(|hex| parse_hex_code(hex)).collect())
+    };
+    let near = near_colors.map(|colors| NearQuery {
+        colors,
+        top: match matches.get_one::<String>("top").unwrap().parse::<usize>() {
+            Ok(top) if top > 0 => top,
+            _ => {
+                eprintln!("The number of images to rank should be a positive number.");
+                process::exit(1);
+            }
+        },
+    });
+    let cluster = if let Some(nb_groups) = matches.get_one::<String>("clusters") {
+        match nb_groups.parse::<usize>() {
+            Ok(nb_groups) if nb_groups > 0 => Some(ClusterCriterion::Count(nb_groups)),
+            _ => {
+                eprintln!("The number of groups should be a positive number.");
+                process::exit(1);
+            }
+        }
+    } else if let Some(threshold) = matches.get_one::<String>("cluster-threshold") {
+        match threshold.parse::<f32>() {
+            Ok(threshold) if threshold >= 0.0 => Some(ClusterCriterion::Threshold(threshold)),
+            _ => {
+                eprintln!("The clustering threshold should be a positive distance.");
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    let aggregate_weights = match (
+        matches.get_flag("aggregate"),
+        matches.get_flag("equal-weights"),
+    ) {
+        (false, _) => None,
+        (true, false) => Some(ImagesWeights::Pixels),
+        (true, true) => Some(ImagesWeights::Equal),
+    };
+    let aggregate_file = matches.get_one::<String>("aggregate-file").cloned();
+    if aggregate_weights.is_some() && as_json && aggregate_file.is_none() {
+        eprintln!(
+            "With the --json flag, the aggregate palette is written to the --aggregate-file file."
+        );
+        process::exit(1);
+    }
+    // Pipes and CI have no terminal to draw in
+    let interactive = !matches.get_flag("no-tui") && io::stdout().is_terminal();
+    let inputs_paths = matches
+        .get_many::<String>("file_path")
+        .map(|paths| paths.map(|path| local_path(path)).collect::<Vec<String>>())
+        .unwrap_or_default();
 
-        ratatui::restore();
-        let images_colors_map: HashMap<String, Result<Vec<Color>, String>> =
-            Arc::try_unwrap(images_colors_map)
-                .expect("There are still other references to the Arc")
-                .into_inner()
-                .expect("Mutex cannot be locked");
+    let mut listed_paths = vec![];
+    if let Some(list_path) = matches.get_one::<String>("from-list") {
+        match File::open(list_path) {
+            Ok(list_file) => listed_paths.extend(read_images_list(io::BufReader::new(list_file))?),
+            Err(_) => {
+                eprintln!(
+                    "Sorry, the list: \"{list_path}\" could not be read. Please, make sure it exists."
+                );
+                process::exit(1);
+            }
+        }
+    }
+    if matches.get_flag("stdin-paths") {
+        listed_paths.extend(read_images_list(io::stdin().lock())?);
+    }
+    for page_url in matches.get_many::<String>("page").unwrap_or_default() {
+        match download_page_images_urls(page_url, &options.download) {
+            Ok(images_urls) => listed_paths.extend(images_urls),
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        }
+    }
+    let from_list = matches.contains_id("from-list")
+        || matches.get_flag("stdin-paths")
+        || matches.contains_id("page");
 
+    // A single repertory is browsed relatively to its path
+    let single_repertory = match inputs_paths.as_slice() {
+        [path] if !from_list && fs::read_dir(path).is_ok() => Some(path.clone()),
+        _ => None,
+    };
+    if from_list
+        || single_repertory.is_some()
+        || inputs_paths.len() > 1
+        || inputs_paths.iter().any(|path| is_glob_pattern(path))
+    {
         /*
-        Sort alphabetically the files
+        Images Collection Case
         */
-        let mut images_names = images_colors_map
-            .iter()
-            .map(|(k, _)| k)
-            .collect::<Vec<&String>>();
-        images_names.sort();
-        let mut images_colors_map_sorted: BTreeMap<String, Result<Vec<Color>, String>> =
-            BTreeMap::<String, Result<Vec<Color>, String>>::new();
-        for image_name in images_names {
-            images_colors_map_sorted
-                .entry(image_name.clone())
-                .or_insert(images_colors_map.get(image_name).unwrap().clone());
-        }
-        let images_colors_map = images_colors_map_sorted;
-        if images_colors_map.is_empty() {
-            let mut none_matching_files_message = String::from("No images file in that repository");
+        let collection_paths = inputs_paths
+            .into_iter()
+            .chain(listed_paths.iter().map(|path| local_path(path)))
+            .collect::<Vec<String>>();
+        // Watching starts before the extraction, not to miss any change
+        let watcher = if watch {
+            match watch_images(&collection_paths, &regex, recursive) {
+                Ok(watcher) => Some(watcher),
+                Err(error_message) => {
+                    eprintln!("{}", error_message);
+                    process::exit(1);
+                }
+            }
+        } else {
+            None
+        };
+        let images_paths = match get_images_paths(collection_paths.into_iter(), &regex, recursive) {
+            Ok(images_paths) => images_paths,
+            Err(error_message) => {
+                eprintln!("{}", error_message);
+                process::exit(1);
+            }
+        };
+        let mut images_colors_map = extract_images_colors(
+            images_paths,
+            nb_colors as u8,
+            &options,
+            !as_json && interactive,
+        );
+        // Watched collections can be filled later
+        if images_colors_map.is_empty() && watcher.is_none() {
+            let mut none_matching_files_message = if single_repertory.is_some() {
+                String::from("No images file in that repository")
+            } else {
+                String::from("No images file matching these paths")
+            };
             if !regex.is_empty() {
                 none_matching_files_message.push_str(" matching this regex pattern");
             }
-            none_matching_files_message.push_str(".");
+            none_matching_files_message.push('.');
             eprintln!("{}", none_matching_files_message);
             process::exit(1);
         }
-
-        enable_raw_mode()?;
-        stdout().execute(EnterAlternateScreen)?;
-        let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
-        let tick_rate = Duration::from_millis(250);
-        let app = App::new(
+        let base_dir = single_repertory.as_deref().unwrap_or("");
+        if as_json || !interactive {
+            let show_canvas = show_canvas && io::stdout().is_terminal();
+            let aggregate = aggregate_weights.map(|images_weights| {
+                aggregate_palette(
+                    &images_colors_map,
+                    nb_colors as u8,
+                    &options,
+                    images_weights,
+                )
+            });
+            let ranking = near.as_ref().map(|near| near.rank(&images_colors_map));
+            let groups = cluster.map(|criterion| {
+                cluster_images(&images_colors_map, criterion, nb_colors as u8, &options)
+            });
+            // The JSON output stays an array of images, the aggregate palette has its own file
+            if let (Some(aggregate), Some(aggregate_file)) = (&aggregate, &aggregate_file) {
+                let aggregate_json = match aggregate {
+                    Ok(palette) => palette.to_json(),
+                    Err(error_message) => serde_json::json!({ "error": error_message }),
+                };
+                if fs::write(aggregate_file, format!("{:#}\n", aggregate_json)).is_err() {
+                    eprintln!(
+                        "Error while writing the aggregate palette file: \"{aggregate_file}\"."
+                    );
+                    process::exit(1);
+                }
+            }
+            if as_json {
+                let images_json = match (&ranking, &groups) {
+                    (Some(ranking), _) => ranked_images_json(&images_colors_map, ranking),
+                    (_, Some(groups)) => images_groups_json(groups),
+                    _ => images_colors_json(&images_colors_map),
+                };
+                println!("{:#}", images_json);
+            } else {
+                if let Some(aggregate) = aggregate {
+                    display_aggregate(aggregate, show_canvas, with_rgb);
+                }
+                match (&ranking, &groups) {
+                    (_, Some(groups)) => {
+                        for (index, group) in groups.iter().enumerate() {
+                            println!("Group {} · {} images", index + 1, group.paths.len());
+                            match &group.palette {
+                                Ok(palette) => {
+                                    display_palette(palette.clone(), show_canvas, with_rgb)
+                                }
+                                Err(error_message) => eprintln!("{}", error_message),
+                            }
+                            for path in &group.paths {
+                                println!("  {}", image_label(path, base_dir));
+                            }
+                        }
+                    }
+                    (Some(ranking), _) => {
+                        for (path, distance) in ranking {
+                            if let Some(Ok(palette)) = images_colors_map.get(path) {
+                                println!("{} · ΔE {distance:.1}", image_label(path, base_dir));
+                                display_palette(palette.clone(), show_canvas, with_rgb);
+                            }
+                        }
+                    }
+                    _ => {
+                        for (path, colors_result) in &images_colors_map {
+                            if let Ok(palette) = colors_result {
+                                println!("{}", image_label(path, base_dir));
+                                display_palette(palette.clone(), show_canvas, with_rgb);
+                            }
+                        }
+                    }
+                }
+            }
+            let nb_failures = report_failures(&images_colors_map, base_dir);
+            if let Some(mut watcher) = watcher {
+                loop {
+                    let changed_paths = watcher.changed_paths();
+                    let known_paths = changed_paths
+                        .iter()
+                        .filter(|path| images_colors_map.contains_key(*path))
+                        .cloned()
+                        .collect::<HashSet<String>>();
+                    update_images_colors(
+                        &mut images_colors_map,
+                        changed_paths.clone(),
+                        &watcher,
+                        nb_colors as u8,
+                        &options,
+                    );
+                    let updated = !changed_paths.is_empty();
+                    for path in changed_paths {
+                        let label = image_label(&path, base_dir);
+                        match images_colors_map.get(&path) {
+                            Some(Ok(palette)) => {
+                                println!("{label}");
+                                display_palette(palette.clone(), show_canvas, with_rgb);
+                            }
+                            Some(Err(error_message)) => {
+                                eprintln!("{label}: {}", error_message.replace('\n', " "));
+                            }
+                            None if known_paths.contains(&path) => println!("{label}: removed"),
+                            None => {}
+                        }
+                    }
+                    if let (Some(images_weights), true) = (aggregate_weights, updated) {
+                        display_aggregate(
+                            aggregate_palette(
+                                &images_colors_map,
+                                nb_colors as u8,
+                                &options,
+                                images_weights,
+                            ),
+                            show_canvas,
+                            with_rgb,
+                        );
+                    }
+                    thread::sleep(WATCH_POLL_INTERVAL);
+                }
+            }
+            if nb_failures > 0 {
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        browse_images_colors(
             images_colors_map,
-            &file_path,
+            base_dir,
             nb_colors as u8,
             with_rgb,
-            &excluded_colors,
-            bc_color,
-        );
-        let res = run_app(&mut terminal, app, tick_rate, &mut ctx);
-
-        disable_raw_mode()?;
-        execute!(terminal.backend_mut(), LeaveAlternateScreen,)?;
-
-        terminal.show_cursor()?;
-
-        if let Err(err) = res {
-            println!("{err:?}");
-        }
-        ratatui::restore();
+            &options,
+            BrowseOptions {
+                watcher,
+                aggregate_weights,
+                near,
+                cluster,
+            },
+        )?;
     } else {
         /*
         Image File Case
          */
-        let image_path_extraction_regex = format!("(?P<link>.*{IMAGES_EXTENSION_REGEX_PATTERN})");
-        let image_regex = Regex::new(&image_path_extraction_regex).unwrap();
-        file_path = match image_regex.captures(&file_path) {
-            Some(fp) => String::from(&fp["link"]),
-            _ => {
-                eprintln!(
-                    "The path you enter is neither that of an existing repertory, nor that of an valid image file (with extension: .png, .jpeg, .jpg ...etc). Please check it, and try again."
-                );
+        let mut file_path = inputs_paths.into_iter().next().unwrap();
+        if near.is_some() || cluster.is_some() {
+            eprintln!(
+                "Only the images of a repertory or a list can be ranked or grouped by colors."
+            );
+            process::exit(1);
+        }
+        if watch {
+            if file_path == STDIN_PATH || is_data_uri(&file_path) || is_remote_url(&file_path) {
+                eprintln!("Only local files and repertories can be watched.");
                 process::exit(1);
             }
-        };
-
-        // Check if it an url
-        let url_parse = Url::parse(&file_path);
-        // File is dropped with dir after the variable goes out of scope
-        let dir = tempfile::tempdir().unwrap();
-        if url_parse.is_ok() {
-            let remote_file_name = Path::new(&file_path).file_name().unwrap().to_str().unwrap();
-            // Create a directory inside of `std::env::temp_dir()`.
-            let tmp_path = dir.path().join(remote_file_name);
-            let tmp_path = tmp_path.as_path().display().to_string();
-            download_file(&file_path, &tmp_path);
-            file_path = tmp_path;
-        }
-
-        let image_file = ImageFile::new(file_path);
-        if image_file.image.is_err() {
-            match image_file.image.err().unwrap() {
-                image::ImageError::IoError(io_error) => match io_error.kind() {
-                    io::ErrorKind::NotFound => {
-                        eprintln!("File not found.\nPlease be sure you provide the correct path!");
-                        process::exit(1);
-                    }
-                    _ => {
-                        eprintln!("Error while opening the file!");
-                        process::exit(1);
-                    }
-                },
+            return watch_image_file(file_path, nb_colors as u8, show_canvas, with_rgb, &options);
+        }
+        let image_file = if file_path == STDIN_PATH {
+            let mut image_data = vec![];
+            let stdin = io::stdin();
+            if stdin.is_terminal() || stdin.lock().read_to_end(&mut image_data).is_err() {
+                image_data.clear();
+            }
+            if image_data.is_empty() {
+                eprintln!("No image could be read from the standard input.");
+                process::exit(1);
+            }
+            ImageFile::from_data(file_path, image_data, None, options.svg_size)
+        } else if is_data_uri(&file_path) {
+            match decode_data_uri(&file_path) {
+                Ok((image_data, media_type)) => ImageFile::from_data(
+                    path_label(&file_path).into_owned(),
+                    image_data,
+                    media_type.as_deref(),
+                    options.svg_size,
+                ),
+                Err(error_message) => {
+                    eprintln!("{}", error_message);
+                    process::exit(1);
+                }
+            }
+        } else if is_remote_url(&file_path) {
+            // Remote images type is sniffed from their content
+            match download_file(&file_path, &options.download)
+                .map_err(|error| error.to_string())
+                .and_then(|remote_image| remote_image.into_image_file(options.svg_size))
+            {
+                Ok(image_file) => image_file,
+                Err(error_message) => {
+                    eprintln!("{}", error_message);
+                    process::exit(1);
+                }
+            }
+        } else {
+            let image_path_extraction_regex =
+                format!("(?P<link>.*{IMAGES_EXTENSION_REGEX_PATTERN})");
+            let image_regex = Regex::new(&image_path_extraction_regex).unwrap();
+            file_path = match image_regex.captures(&file_path) {
+                Some(fp) => String::from(&fp["link"]),
                 _ => {
-                    eprintln!("Error while opening the file!");
+                    eprintln!(
+                        "The path you enter is neither that of an existing repertory, nor that of an valid image file (with extension: .png, .jpeg, .jpg ...etc). Please check it, and try again."
+                    );
                     process::exit(1);
                 }
+            };
+            ImageFile::new(file_path, options.svg_size)
+        };
+        if let Some(error_message) = image_file.open_error(options.frames) {
+            eprintln!("{}", error_message);
+            process::exit(1);
+        }
+        // Per-frame colors extracting
+        if let (Some(selector), false) = (options.frames, matches.get_flag("aggregate-frames")) {
+            let frames_colors = image_file.get_frames_colors(nb_colors as u8, &options, selector);
+            if let Err(extraction_error_message) = frames_colors {
+                eprintln!("{}", extraction_error_message);
+                process::exit(1);
             }
+            if as_json {
+                let frames_json = frames_colors
+                    .unwrap()
+                    .iter()
+                    .map(|(frame, palette)| {
+                        let mut frame_json = palette.to_json();
+                        frame_json["frame"] = frame.number.into();
+                        frame_json["duration_ms"] = frame.duration_ms.into();
+                        frame_json
+                    })
+                    .collect::<serde_json::Value>();
+                println!("{:#}", frames_json);
+                return Ok(());
+            }
+            for (frame, palette) in frames_colors.unwrap() {
+                let label = format!("#{} ({} ms)", frame.number, frame.duration_ms.round());
+                ColorsCanvas::new(palette.colors, show_canvas, with_rgb, false)
+                    .display_frame(&label);
+            }
+            return Ok(());
         }
         // Colors extracting
-        let colors = image_file.get_colors_from_images(nb_colors as u8, &excluded_colors, bc_color);
+        let colors = image_file.get_colors_from_images(nb_colors as u8, &options);
         if let Err(extraction_error_message) = colors {
             eprintln!("{}", extraction_error_message);
             process::exit(1);
         }
-        let colors = colors.unwrap();
-        let cv = ColorsCanvas::new(colors, show_canvas, with_rgb, false);
-        cv.display();
+        let palette = colors.unwrap();
+        if as_json {
+            println!("{:#}", palette.to_json());
+            return Ok(());
+        }
+        display_palette(palette, show_canvas, with_rgb);
     }
 
     Ok(())
 }
 
+/// Cache subcommands
+fn run_cache_command(cache_matches: &ArgMatches) -> io::Result<()> {
+    if let Some(("prune", prune_matches)) = cache_matches.subcommand() {
+        let max_age_days = match prune_matches
+            .get_one::<String>("older-than")
+            .unwrap()
+            .parse::<u64>()
+        {
+            Ok(days) => days,
+            Err(_) => {
+                eprintln!("The age of the palettes to remove should be a number of days.");
+                process::exit(1);
+            }
+        };
+        let cache_dir = PaletteCache::default_dir().filter(|dir| dir.exists());
+        let (removed, freed) = match cache_dir {
+            Some(cache_dir) => {
+                prune_cache(&cache_dir, Duration::from_secs(max_age_days * 24 * 60 * 60))?
+            }
+            None => (0, 0),
+        };
+        println!(
+            "{removed} cached palettes removed ({} KB freed).",
+            freed.div_ceil(1024)
+        );
+    }
+    Ok(())
+}
+
+/// Index database option, shared by the index subcommands
+fn index_path_arg() -> Arg {
+    Arg::new("db")
+        .long("db")
+        .value_name("INDEX_PATH")
+        .num_args(1)
+        .help("Index database file, in the user data directory by default")
+}
+
+/// Index database of the index subcommands
+fn open_index(index_matches: &ArgMatches) -> PaletteIndex {
+    let index_path = match index_matches.get_one::<String>("db") {
+        Some(index_path) => PathBuf::from(index_path),
+        None => match PaletteIndex::default_path() {
+            Some(index_path) => index_path,
+            None => {
+                eprintln!("No user data directory, please provide the index path with --db.");
+                process::exit(1);
+            }
+        },
+    };
+    match PaletteIndex::open(&index_path) {
+        Ok(index) => index,
+        Err(error_message) => {
+            eprintln!("{}", error_message);
+            process::exit(1);
+        }
+    }
+}
+
+/// Extract the new and modified images of the repertories in the index
+fn run_index_command(index_matches: &ArgMatches) -> io::Result<()> {
+    let nb_colors = match index_matches
+        .get_one::<String>("nb-colors")
+        .unwrap()
+        .parse::<u8>()
+    {
+        Ok(nb) if nb > 1 => nb,
+        _ => {
+            eprintln!("The number of colors should be a number between 2 and 255.");
+            process::exit(1);
+        }
+    };
+    let repertories = index_matches
+        .get_many::<String>("repertories")
+        .unwrap()
+        .map(|path| local_path(path))
+        .collect::<Vec<String>>();
+    let mut images_paths = vec![];
+    for repertory in &repertories {
+        match get_matching_files(repertory, "", true) {
+            Ok(repertory_images) => images_paths.extend(repertory_images),
+            Err(error_message) => {
+                eprintln!("{}", error_message);
+                process::exit(1);
+            }
+        }
+    }
+    let mut index = open_index(index_matches);
+    match index.update(
+        &repertories,
+        images_paths,
+        nb_colors,
+        &ExtractionOptions::default(),
+    ) {
+        Ok(summary) => {
+            println!(
+                "{} images indexed, {} unchanged, {} removed.",
+                summary.indexed, summary.unchanged, summary.removed
+            );
+            if summary.failed > 0 {
+                eprintln!("{} images could not be extracted.", summary.failed);
+            }
+        }
+        Err(error_message) => {
+            eprintln!("{}", error_message);
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
+
+/// Search the indexed images
+fn run_query_command(query_matches: &ArgMatches) -> io::Result<()> {
+    let near = if let Some(palette_path) = query_matches.get_one::<String>("near-palette") {
+        match read_palette_file(palette_path) {
+            Ok(colors) => Some(colors),
+            Err(error_message) => {
+                eprintln!("{}", error_message);
+                process::exit(1);
+            }
+        }
+    } else {
+        query_matches
+            .get_many::<String>("near")
+            .map(|hex_codes| hex_codes.map(|hex| parse_hex_code(hex)).collect())
+    };
+    let hue = query_matches.get_one::<String>("hue").map(|hue_range| {
+        let hue_bounds = hue_range.split_once('-').and_then(|(min, max)| {
+            Some((
+                min.trim().parse::<f32>().ok()?,
+                max.trim().parse::<f32>().ok()?,
+            ))
+        });
+        match hue_bounds {
+            Some((min, max)) if (0.0..=360.0).contains(&min) && (0.0..=360.0).contains(&max) => {
+                (min, max)
+            }
+            _ => {
+                eprintln!("The hue range should be two angles between 0 and 360, like 200-250.");
+                process::exit(1);
+            }
+        }
+    });
+    let min_share = match query_matches
+        .get_one::<String>("min-share")
+        .unwrap()
+        .parse::<f32>()
+    {
+        Ok(share) if (0.0..=1.0).contains(&share) => share,
+        _ => {
+            eprintln!("The minimum share of the hue range should be between 0 and 1.");
+            process::exit(1);
+        }
+    };
+    let colorfulness = |arg_name: &str| {
+        query_matches
+            .get_one::<String>(arg_name)
+            .map(|colorfulness| match colorfulness.parse::<f32>() {
+                Ok(colorfulness) if colorfulness >= 0.0 => colorfulness,
+                _ => {
+                    eprintln!("The --{arg_name} should be a positive number.");
+                    process::exit(1);
+                }
+            })
+    };
+    let top = match query_matches
+        .get_one::<String>("top")
+        .unwrap()
+        .parse::<usize>()
+    {
+        Ok(top) if top > 0 => top,
+        _ => {
+            eprintln!("The number of images to keep should be a positive number.");
+            process::exit(1);
+        }
+    };
+    let query = IndexQuery {
+        near,
+        hue,
+        min_share,
+        min_colorfulness: colorfulness("min-colorfulness"),
+        max_colorfulness: colorfulness("max-colorfulness"),
+        name: query_matches.get_one::<String>("name").cloned(),
+        top,
+    };
+    let images = match open_index(query_matches).query(&query) {
+        Ok(images) => images,
+        Err(error_message) => {
+            eprintln!("{}", error_message);
+            process::exit(1);
+        }
+    };
+    if query_matches.get_flag("json") {
+        let images_json = images
+            .iter()
+            .map(|image| image.to_json())
+            .collect::<serde_json::Value>();
+        println!("{:#}", images_json);
+        return Ok(());
+    }
+    let show_canvas = query_matches.get_flag("canvas") && io::stdout().is_terminal();
+    for image in images {
+        match image.distance {
+            Some(distance) => println!("{} · ΔE {distance:.1}", image.path),
+            None => println!("{}", image.path),
+        }
+        display_palette(image.palette, show_canvas, query_matches.get_flag("rgb"));
+    }
+    Ok(())
+}
+
+/// Serve the colors extraction over HTTP
+fn run_serve_command(serve_matches: &ArgMatches) -> io::Result<()> {
+    let port = match serve_matches
+        .get_one::<String>("port")
+        .unwrap()
+        .parse::<u16>()
+    {
+        Ok(port) => port,
+        Err(_) => {
+            eprintln!("The port should be a number between 0 and 65535.");
+            process::exit(1);
+        }
+    };
+    let workers = match serve_matches.get_one::<String>("workers") {
+        Some(workers) => match workers.parse::<usize>() {
+            Ok(workers) if workers > 0 => workers,
+            _ => {
+                eprintln!("The number of workers should be a positive number.");
+                process::exit(1);
+            }
+        },
+        None => thread::available_parallelism().map_or(1, |cpus| cpus.get()),
+    };
+    let max_upload_size =
+        match parse_size(serve_matches.get_one::<String>("max-upload-size").unwrap()) {
+            Ok(size) => size,
+            Err(error_message) => {
+                eprintln!("{}", error_message);
+                process::exit(1);
+            }
+        };
+    let server_options = ServerOptions {
+        host: serve_matches.get_one::<String>("host").unwrap().clone(),
+        port,
+        workers,
+        max_upload_size,
+        allow_private_urls: serve_matches.get_flag("allow-private-urls"),
+    };
+    if let Err(error_message) = serve(server_options, default_options()) {
+        eprintln!("{}", error_message);
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Extraction options of the requests of the long-lived subcommands, with the palettes cache
+fn default_options() -> ExtractionOptions {
+    ExtractionOptions {
+        palette_cache: PaletteCache::default_dir()
+            .and_then(|dir| PaletteCache::new(dir, false, DEFAULT_PALETTE_CACHE_SIZE).ok()),
+        ..ExtractionOptions::default()
+    }
+}
+
+/// Color of a hexadecimal code argument, exiting on an invalid code
+fn parse_hex_code(hex_code: &str) -> Color {
+    match Color::hex_to_rgb(hex_code) {
+        Ok(color) => color,
+        Err(error_message) => {
+            eprintln!("{error_message}\nPlease provide a valid hex code, and try again!");
+            process::exit(1);
+        }
+    }
+}
+
+/// Palette displaying, with its background colors if any
+fn display_palette(palette: Palette, show_canvas: bool, with_rgb: bool) {
+    let cv = ColorsCanvas::new(palette.colors, show_canvas, with_rgb, false);
+    cv.display();
+    if !palette.background.is_empty() {
+        print!("Background: ");
+        ColorsCanvas::new(palette.background, false, with_rgb, false).display();
+    }
+}
+
+/// Aggregate palette displaying, before the images ones
+fn display_aggregate(aggregate: Result<Palette, String>, show_canvas: bool, with_rgb: bool) {
+    match aggregate {
+        Ok(palette) => {
+            println!("Aggregate palette");
+            display_palette(palette, show_canvas, with_rgb);
+        }
+        Err(error_message) => eprintln!("Aggregate palette: {error_message}"),
+    }
+}
+
+/// Image path relatively to the browsed repertory
+fn image_label<'a>(path: &'a str, base_dir: &str) -> &'a str {
+    if base_dir.is_empty() {
+        return path;
+    }
+    path.strip_prefix(base_dir)
+        .map(|label| label.trim_start_matches('/'))
+        .unwrap_or(path)
+}
+
+/// Summary of the failed extractions, returning their number
+fn report_failures(images_colors_map: &ImagesColors, base_dir: &str) -> usize {
+    let failures = images_colors_map
+        .iter()
+        .filter_map(|(path, colors_result)| Some((path, colors_result.as_ref().err()?)))
+        .collect::<Vec<_>>();
+    if failures.is_empty() {
+        return 0;
+    }
+    eprintln!(
+        "{} of {} images failed:",
+        failures.len(),
+        images_colors_map.len()
+    );
+    for (path, error_message) in &failures {
+        eprintln!(
+            "  {}: {}",
+            image_label(path, base_dir),
+            error_message.replace('\n', " ")
+        );
+    }
+    failures.len()
+}
+
+/// Redraw the colors of an image file each time it changes
+fn watch_image_file(
+    file_path: String,
+    nb_colors: u8,
+    show_canvas: bool,
+    with_rgb: bool,
+    options: &ExtractionOptions,
+) -> io::Result<()> {
+    let mut watcher =
+        match ImagesWatcher::new(&[], &[], std::slice::from_ref(&file_path), "", false) {
+            Ok(watcher) => watcher,
+            Err(error_message) => {
+                eprintln!("{}", error_message);
+                process::exit(1);
+            }
+        };
+    let is_terminal = io::stdout().is_terminal();
+    loop {
+        if is_terminal {
+            io::stdout()
+                .execute(Clear(ClearType::All))?
+                .execute(MoveTo(0, 0))?;
+        }
+        match get_extracted_colors(file_path.clone(), nb_colors, options) {
+            Ok(palette) => display_palette(palette, show_canvas, with_rgb),
+            Err(error_message) => eprintln!("{}", error_message),
+        }
+        println!("\nWatching \"{file_path}\" for changes. Press Ctrl+C to exit.");
+        while !watcher.changed_paths().contains(&file_path) {
+            thread::sleep(WATCH_POLL_INTERVAL);
+        }
+    }
+}
+
+/// Watcher of the repertories, glob patterns and local files of a collection
+fn watch_images(
+    collection_paths: &[String],
+    regex_pattern: &str,
+    recursive: bool,
+) -> Result<ImagesWatcher, String> {
+    let (mut directories, mut patterns, mut files) = (vec![], vec![], vec![]);
+    for path in collection_paths {
+        if Path::new(path).is_dir() {
+            directories.push(path.clone());
+        } else if is_glob_pattern(path) {
+            patterns.push(path.clone());
+        } else if !is_remote_url(path) && !is_data_uri(path) {
+            files.push(path.clone());
+        }
+    }
+    ImagesWatcher::new(&directories, &patterns, &files, regex_pattern, recursive)
+}
+
+/// Whether a path is a glob pattern, URIs are never patterns
+fn is_glob_pattern(path: &str) -> bool {
+    !is_remote_url(path) && !is_data_uri(path) && path.contains(['*', '?', '['])
+}
+
+/// Local path of a file:// URI, other paths are unchanged
+fn local_path(path: &str) -> String {
+    file_uri_path(path).unwrap_or_else(|| path.to_string())
+}
+
+/// Images paths of the repertories, glob patterns and files, without duplicates
+fn get_images_paths(
+    paths: impl Iterator<Item = String>,
+    regex_pattern: &str,
+    recursive: bool,
+) -> Result<Vec<String>, String> {
+    let images_re = Regex::new(IMAGES_EXTENSION_REGEX_PATTERN).unwrap();
+    let mut images_paths = Vec::new();
+    for path in paths {
+        if Path::new(&path).is_dir() {
+            images_paths.extend(get_matching_files(&path, regex_pattern, recursive)?);
+        } else if is_glob_pattern(&path) {
+            let glob_paths = match glob::glob(&path) {
+                Ok(glob_paths) => glob_paths,
+                Err(_) => return Err(format!("Invalid glob pattern: {path}")),
+            };
+            for glob_path in glob_paths.filter_map(Result::ok) {
+                let glob_path = glob_path.display().to_string();
+                if Path::new(&glob_path).is_file() && images_re.is_match(&glob_path) {
+                    images_paths.push(glob_path);
+                }
+            }
+        } else {
+            images_paths.push(path);
+        }
+    }
+    // The same file can be reached through different paths
+    let mut seen_paths = HashSet::new();
+    images_paths.retain(|path| {
+        let canonical_path = fs::canonicalize(path)
+            .map(|canonical_path| canonical_path.display().to_string())
+            .unwrap_or_else(|_| path.clone());
+        seen_paths.insert(canonical_path)
+    });
+    Ok(images_paths)
+}
+
 /// Repertory files matched a regex pattern
 fn get_matching_files(
     directory: &str,
@@ -397,43 +1722,3 @@ fn get_matching_files(
     }
     Ok(result_files)
 }
-
-/// Extract colors from file path
-/// used for iterated  extractions in repertory case
-fn get_extracted_colors<'a>(
-    file_path: String,
-    nb_colors: u8,
-    excluded_colors: &'a Vec<Color>,
-    bc_color: Option<&'a Color>,
-) -> Result<Vec<Color>, String> {
-    let image_file = ImageFile::new(file_path);
-    if image_file.image.is_err() {
-        match image_file.image.err().unwrap() {
-            image::ImageError::IoError(io_error) => match io_error.kind() {
-                io::ErrorKind::NotFound => {
-                    return Err(
-                        "File not found.\nPlease be sure you provide the correct path!".to_string(),
-                    );
-                }
-                _ => {
-                    return Err("Error while opening the file!".to_string());
-                }
-            },
-            _ => {
-                return Err("Error while opening the file!".to_string());
-            }
-        }
-    }
-    // Colors extracting
-    image_file.get_colors_from_images(nb_colors, excluded_colors, bc_color)
-}
-
-/// Download remote file
-fn download_file(file_link: &str, file_dest: &str) {
-    let mut resp = ureq::get(file_link)
-        .call()
-        .expect("request failed")
-        .into_reader();
-    let mut out = File::create(file_dest).expect("failed to create file");
-    io::copy(&mut resp, &mut out).expect("failed to copy content");
-}
@@ -0,0 +1,147 @@
+use image::{imageops::FilterType, GrayImage};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+// Scaled masks kept, beyond which they are forgotten
+const MAX_SCALED_MASKS: usize = 16;
+
+/// Segmentation mask restricting the pixels fed to the quantizer
+/// A single mask file is decoded once, and the weights of the last
+/// mask files and image sizes are kept, as the frames and redraws reuse them.
+#[derive(Clone)]
+pub struct Mask {
+    path: String,
+    threshold: u8,
+    weighted: bool,
+    /// Decoded mask, unless the path is a repertory of masks
+    image: Option<Arc<GrayImage>>,
+    scaled: Arc<Mutex<ScaledMasks>>,
+}
+
+/// Weights of the mask files scaled to the images sizes
+type ScaledMasks = HashMap<(PathBuf, u32, u32), Vec<f32>>;
+
+impl fmt::Debug for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mask")
+            .field("path", &self.path)
+            .field("threshold", &self.threshold)
+            .field("weighted", &self.weighted)
+            .finish()
+    }
+}
+
+impl Mask {
+    /// Mask of a file, decoded now, or of a repertory of masks named like the images
+    #[cfg(feature = "native")]
+    pub fn open(path: String, threshold: u8, weighted: bool) -> Result<Self, String> {
+        let image = if Path::new(&path).is_dir() {
+            None
+        } else {
+            Some(Arc::new(open_mask_file(Path::new(&path))?))
+        };
+        Ok(Mask {
+            path,
+            threshold,
+            weighted,
+            image,
+            scaled: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Mask file of an image: the mask itself,
+    /// or the same-named file in a repertory of masks
+    fn mask_file_for(&self, image_path: &str) -> PathBuf {
+        let mask_path = Path::new(&self.path);
+        if self.image.is_none() {
+            if let Some(file_name) = Path::new(image_path).file_name() {
+                return mask_path.join(file_name);
+            }
+        }
+        mask_path.to_path_buf()
+    }
+
+    /// Modification time and size of the mask file of an image
+    #[cfg(feature = "native")]
+    pub fn file_state(&self, image_path: &str) -> Option<(u64, u64)> {
+        crate::palette_cache::file_state(&self.mask_file_for(image_path))
+    }
+
+    /// Pixels weights (between 0 and 1) of the mask scaled to the image size
+    pub fn pixels_weights(
+        &self,
+        image_path: &str,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<f32>, String> {
+        let scaled_key = (self.mask_file_for(image_path), width, height);
+        if let Some(weights) = self.scaled.lock().unwrap().get(&scaled_key) {
+            return Ok(weights.clone());
+        }
+        // Decoded and resized unlocked, not to hold the parallel extractions
+        let mask_file = &scaled_key.0;
+        let repertory_mask;
+        let mask = match &self.image {
+            Some(image) => image.as_ref(),
+            None => {
+                repertory_mask = open_mask_file(mask_file)?;
+                &repertory_mask
+            }
+        };
+        let weights = if mask.dimensions() != (width, height) {
+            self.luma_to_weights(&image::imageops::resize(
+                mask,
+                width,
+                height,
+                FilterType::Nearest,
+            ))
+        } else {
+            self.luma_to_weights(mask)
+        };
+        let mut scaled = self.scaled.lock().unwrap();
+        if scaled.len() >= MAX_SCALED_MASKS {
+            scaled.clear();
+        }
+        scaled.insert(scaled_key, weights.clone());
+        Ok(weights)
+    }
+
+    fn luma_to_weights(&self, mask: &GrayImage) -> Vec<f32> {
+        mask.pixels()
+            .map(|pixel| {
+                let luma = pixel.0[0];
+                if luma <= self.threshold {
+                    0.0
+                } else if self.weighted {
+                    luma as f32 / 255.0
+                } else {
+                    1.0
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "native")]
+fn open_mask_file(mask_file: &Path) -> Result<GrayImage, String> {
+    match image::open(mask_file) {
+        Ok(mask) => Ok(mask.to_luma8()),
+        Err(_) => Err(format!(
+            "Error while opening the mask file: \"{}\".",
+            mask_file.display()
+        )),
+    }
+}
+
+// Without file system, the masks files are never opened
+#[cfg(not(feature = "native"))]
+fn open_mask_file(mask_file: &Path) -> Result<GrayImage, String> {
+    Err(format!(
+        "The mask file: \"{}\" cannot be opened without the native feature.",
+        mask_file.display()
+    ))
+}
@@ -0,0 +1,135 @@
+use color_thief::Color;
+use std::fs;
+
+use crate::images_batch::ImagesColors;
+use crate::{ColorTrait, Palette};
+
+/// Colors to rank the images of a collection by, with the number of images to keep
+#[derive(Debug, Clone)]
+pub struct NearQuery {
+    pub colors: Vec<Color>,
+    pub top: usize,
+}
+
+impl NearQuery {
+    /// Paths of the closest images palettes, with their distance to the query colors
+    pub fn rank(&self, images_colors_map: &ImagesColors) -> Vec<(String, f32)> {
+        let mut ranking = images_colors_map
+            .iter()
+            .filter_map(|(path, colors_result)| {
+                let palette = colors_result.as_ref().ok()?;
+                Some((path.clone(), palette_distance(palette, &self.colors)))
+            })
+            .collect::<Vec<(String, f32)>>();
+        ranking.sort_by(|(_, distance1), (_, distance2)| distance1.total_cmp(distance2));
+        ranking.truncate(self.top);
+        ranking
+    }
+}
+
+/// Colors of a GIMP palette (.gpl), or of a list of hexadecimal codes
+pub fn read_palette_file(palette_path: &str) -> Result<Vec<Color>, String> {
+    let palette_content = fs::read_to_string(palette_path).map_err(|_| {
+        format!("Sorry, the palette: \"{palette_path}\" could not be read. Please, make sure it exists.")
+    })?;
+    let mut colors = vec![];
+    for line in palette_content.lines().map(str::trim) {
+        // Hexadecimal codes, with or without #
+        if let Ok(color) = Color::hex_to_rgb(line) {
+            colors.push(color);
+            continue;
+        }
+        // Comments
+        if line.starts_with('#') {
+            continue;
+        }
+        // Header and attributes of the GIMP palettes
+        if line.is_empty() || line.starts_with("GIMP Palette") || line.contains(':') {
+            continue;
+        }
+        // "R G B Name" lines
+        let channels = line
+            .split_whitespace()
+            .take(3)
+            .map(|channel| channel.parse::<u8>().ok())
+            .collect::<Option<Vec<u8>>>()
+            .filter(|channels| channels.len() == 3);
+        match channels {
+            Some(channels) => colors.push(Color {
+                r: channels[0],
+                g: channels[1],
+                b: channels[2],
+            }),
+            None => return Err(format!("Invalid palette line: \"{line}\".")),
+        }
+    }
+    if colors.is_empty() {
+        return Err(format!("No colors in the palette: \"{palette_path}\"."));
+    }
+    Ok(colors)
+}
+
+/// Population-weighted distance between a palette and the query colors:
+/// the mean ΔE of each palette color to its nearest query color, and conversely
+pub fn palette_distance(palette: &Palette, query_colors: &[Color]) -> f32 {
+    weighted_distance(&weighted_lab(palette), &query_lab(query_colors))
+}
+
+/// CIELAB query colors, weighed equally
+pub(crate) fn query_lab(query_colors: &[Color]) -> Vec<([f32; 3], f32)> {
+    query_colors
+        .iter()
+        .map(|color| (color.to_lab(), 1.0 / query_colors.len() as f32))
+        .collect()
+}
+
+/// Population-weighted distance between two palettes
+pub fn palettes_distance(palette1: &Palette, palette2: &Palette) -> f32 {
+    weighted_distance(&weighted_lab(palette1), &weighted_lab(palette2))
+}
+
+/// CIELAB colors of a palette, with their proportions
+pub(crate) fn weighted_lab(palette: &Palette) -> Vec<([f32; 3], f32)> {
+    palette
+        .colors
+        .iter()
+        .enumerate()
+        .map(|(index, color)| {
+            // Palettes without proportions weigh their colors equally
+            let proportion = palette
+                .proportions
+                .get(index)
+                .copied()
+                .unwrap_or(1.0 / palette.colors.len() as f32);
+            (color.to_lab(), proportion)
+        })
+        .collect()
+}
+
+pub(crate) fn weighted_distance(colors1: &[([f32; 3], f32)], colors2: &[([f32; 3], f32)]) -> f32 {
+    if colors1.is_empty() || colors2.is_empty() {
+        return f32::INFINITY;
+    }
+    let nearest_deltas = |colors: &[([f32; 3], f32)], others: &[([f32; 3], f32)]| {
+        colors
+            .iter()
+            .map(|(color, weight)| {
+                let nearest_delta = others
+                    .iter()
+                    .map(|(other, _)| delta_e(color, other))
+                    .fold(f32::INFINITY, f32::min);
+                weight * nearest_delta
+            })
+            .sum::<f32>()
+    };
+    (nearest_deltas(colors1, colors2) + nearest_deltas(colors2, colors1)) / 2.0
+}
+
+/// CIE76 color difference
+fn delta_e(lab1: &[f32; 3], lab2: &[f32; 3]) -> f32 {
+    lab1.iter()
+        .zip(lab2)
+        .map(|(channel1, channel2)| (channel1 - channel2).powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
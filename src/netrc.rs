@@ -0,0 +1,86 @@
+use std::{env, fs, path::PathBuf};
+
+/// Login of a machine in a netrc file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetrcLogin {
+    /// Machine name, None for the default login
+    machine: Option<String>,
+    pub login: String,
+    pub password: String,
+}
+
+/// Netrc file path, from the NETRC variable or in the home directory
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".netrc"))
+}
+
+/// Logins of the user netrc file, read once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Netrc {
+    logins: Vec<NetrcLogin>,
+}
+
+impl Netrc {
+    /// Logins of the user netrc file, none if it cannot be read
+    pub fn load() -> Self {
+        let content = netrc_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        Netrc {
+            logins: parse_netrc(&content),
+        }
+    }
+
+    /// Login of the host, or the default login
+    pub fn login(&self, host: &str) -> Option<&NetrcLogin> {
+        let host_login = self.logins.iter().find(|login| {
+            login
+                .machine
+                .as_deref()
+                .is_some_and(|machine| machine.eq_ignore_ascii_case(host))
+        });
+        host_login.or_else(|| self.logins.iter().find(|login| login.machine.is_none()))
+    }
+}
+
+fn parse_netrc(content: &str) -> Vec<NetrcLogin> {
+    let mut logins: Vec<NetrcLogin> = vec![];
+    let mut in_macro = false;
+    for line in content.lines() {
+        // Macros definitions end with an empty line
+        if in_macro {
+            in_macro = !line.trim().is_empty();
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        while let Some(token) = tokens.next() {
+            match (token, logins.last_mut()) {
+                ("machine", _) => logins.push(NetrcLogin {
+                    machine: Some(tokens.next().unwrap_or_default().to_string()),
+                    ..NetrcLogin::default()
+                }),
+                ("default", _) => logins.push(NetrcLogin::default()),
+                ("login", Some(login)) => {
+                    login.login = tokens.next().unwrap_or_default().to_string();
+                }
+                ("password", Some(login)) => {
+                    login.password = tokens.next().unwrap_or_default().to_string();
+                }
+                ("account", _) => {
+                    tokens.next();
+                }
+                ("macdef", _) => {
+                    in_macro = true;
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+    logins
+}
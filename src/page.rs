@@ -0,0 +1,211 @@
+use regex::Regex;
+use std::sync::OnceLock;
+use url::Url;
+
+use crate::remote::{download_file, DownloadError, DownloadOptions};
+
+const IMG_TAG_REGEX_PATTERN: &str = r"(?is)<(?:img|source)\b[^>]*>";
+// The <source> tags of the videos and audios are not images
+const MEDIA_ELEMENT_REGEX_PATTERN: &str = r"(?is)<video\b.*?</video\s*>|<audio\b.*?</audio\s*>";
+const META_TAG_REGEX_PATTERN: &str = r"(?is)<meta\b[^>]*>";
+const BASE_TAG_REGEX_PATTERN: &str = r"(?is)<base\b[^>]*>";
+const CSS_BACKGROUND_REGEX_PATTERN: &str =
+    r#"(?is)background(?:-image)?\s*:[^;}]*?url\(\s*['"]?([^'")]+?)['"]?\s*\)"#;
+const ATTRIBUTE_REGEX_PATTERN: &str =
+    r#"(?is)[\s<]([^\s=>/]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#;
+// Regexes compiled once for all the pages
+static IMG_TAG_REGEX: OnceLock<Regex> = OnceLock::new();
+static MEDIA_ELEMENT_REGEX: OnceLock<Regex> = OnceLock::new();
+static META_TAG_REGEX: OnceLock<Regex> = OnceLock::new();
+static BASE_TAG_REGEX: OnceLock<Regex> = OnceLock::new();
+static CSS_BACKGROUND_REGEX: OnceLock<Regex> = OnceLock::new();
+static ATTRIBUTE_REGEX: OnceLock<Regex> = OnceLock::new();
+
+// Open Graph and Twitter cards images
+const META_IMAGE_PROPERTIES: [&str; 4] = [
+    "og:image",
+    "og:image:url",
+    "og:image:secure_url",
+    "twitter:image",
+];
+
+/// Images URLs of a remote HTML page
+pub fn download_page_images_urls(
+    page_url: &str,
+    options: &DownloadOptions,
+) -> Result<Vec<String>, DownloadError> {
+    let page = download_file(page_url, options)?;
+    let html = String::from_utf8_lossy(&page.data);
+    // The relative links are resolved against the redirected page URL,
+    // which was parsed while downloading
+    Ok(page_images_urls(
+        &html,
+        &Url::parse(&page.final_url).unwrap(),
+    ))
+}
+
+fn regex(regex: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    regex.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+/// Images URLs (or data URIs) of the <img> and <source> tags (src and srcset),
+/// the Open Graph images and the CSS background images, resolved against the base URL
+pub fn page_images_urls(html: &str, page_url: &Url) -> Vec<String> {
+    let base_url = regex(&BASE_TAG_REGEX, BASE_TAG_REGEX_PATTERN)
+        .find(html)
+        .and_then(|base_tag| attribute_value(base_tag.as_str(), "href"))
+        .and_then(|href| page_url.join(&href).ok())
+        .unwrap_or_else(|| page_url.clone());
+
+    let mut links = vec![];
+    let images_html =
+        regex(&MEDIA_ELEMENT_REGEX, MEDIA_ELEMENT_REGEX_PATTERN).replace_all(html, "");
+    for img_tag in regex(&IMG_TAG_REGEX, IMG_TAG_REGEX_PATTERN).find_iter(&images_html) {
+        let img_tag = img_tag.as_str();
+        links.extend(attribute_value(img_tag, "src"));
+        if let Some(srcset) = attribute_value(img_tag, "srcset") {
+            links.extend(srcset_urls(&srcset));
+        }
+    }
+    for meta_tag in regex(&META_TAG_REGEX, META_TAG_REGEX_PATTERN).find_iter(html) {
+        let meta_tag = meta_tag.as_str();
+        let property = attribute_value(meta_tag, "property")
+            .or_else(|| attribute_value(meta_tag, "name"))
+            .map(|property| property.to_lowercase());
+        if property.is_some_and(|property| META_IMAGE_PROPERTIES.contains(&property.as_str())) {
+            links.extend(attribute_value(meta_tag, "content"));
+        }
+    }
+    for background in regex(&CSS_BACKGROUND_REGEX, CSS_BACKGROUND_REGEX_PATTERN).captures_iter(html)
+    {
+        links.push(decode_html_entities(&background[1]));
+    }
+
+    let mut images_urls = Vec::<String>::new();
+    for link in links {
+        let Ok(image_url) = base_url.join(link.trim()) else {
+            continue;
+        };
+        if !matches!(image_url.scheme(), "http" | "https" | "data") {
+            continue;
+        }
+        let image_url = image_url.to_string();
+        if !images_urls.contains(&image_url) {
+            images_urls.push(image_url);
+        }
+    }
+    images_urls
+}
+
+/// Value of a tag attribute, quoted or not
+fn attribute_value(tag: &str, name: &str) -> Option<String> {
+    let captures = regex(&ATTRIBUTE_REGEX, ATTRIBUTE_REGEX_PATTERN)
+        .captures_iter(tag)
+        .find(|captures| captures[1].eq_ignore_ascii_case(name))?;
+    let value = captures
+        .get(2)
+        .or_else(|| captures.get(3))
+        .or_else(|| captures.get(4))?;
+    Some(decode_html_entities(value.as_str()))
+}
+
+/// URLs of the srcset candidates, parsed like browsers do
+/// A candidate URL ends at a whitespace, where its width or density descriptor starts,
+/// so that the commas inside the URLs are kept.
+fn srcset_urls(srcset: &str) -> Vec<String> {
+    let mut urls = vec![];
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() {
+            return urls;
+        }
+        let url_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let url = &rest[..url_end];
+        rest = &rest[url_end..];
+        // A trailing comma ends the candidate, without descriptors
+        if url.ends_with(',') {
+            urls.push(url.trim_end_matches(',').to_string());
+            continue;
+        }
+        urls.push(url.to_string());
+        // The descriptors end at the next comma outside of parentheses
+        let mut in_parentheses = false;
+        let descriptors_end = rest.find(|c: char| {
+            match c {
+                '(' => in_parentheses = true,
+                ')' => in_parentheses = false,
+                ',' if !in_parentheses => return true,
+                _ => {}
+            }
+            false
+        });
+        rest = descriptors_end.map_or("", |end| &rest[end + 1..]);
+    }
+}
+
+/// Decode the HTML entities found in URLs
+fn decode_html_entities(value: &str) -> String {
+    value
+        .replace("&amp;", "&")
+        .replace("&#38;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::tests::{response, serve};
+    use std::time::Duration;
+
+    const PAGE_FIXTURE: &str = include_str!("../tests/fixtures/page.html");
+
+    #[test]
+    fn fixture_images_urls() {
+        let page_url = Url::parse("https://www.example.com/blog/post.html").unwrap();
+        assert_eq!(
+            page_images_urls(PAGE_FIXTURE, &page_url),
+            [
+                "https://www.example.com/blog/logo.png",
+                "https://www.example.com/blog/photo.jpg",
+                "https://res.example.com/image/upload/w_400,h_300/photo.jpg",
+                "https://res.example.com/image/upload/w_800,h_600/photo.jpg",
+                "https://www.example.com/blog/small.png",
+                "https://www.example.com/blog/large.png",
+                "https://www.example.com/blog/banner.avif",
+                "https://www.example.com/blog/banner.png",
+                "https://www.example.com/images/cover.jpg",
+                "https://cdn.example.com/card.png",
+                "https://www.example.com/blog/hero.webp",
+            ]
+        );
+    }
+
+    #[test]
+    fn srcset_candidates() {
+        assert_eq!(
+            srcset_urls("a.png 1x,b.png 2x , data:image/png;base64,AAAA 3x"),
+            ["a.png", "b.png", "data:image/png;base64,AAAA"]
+        );
+        assert_eq!(srcset_urls("a.png,b.png"), ["a.png,b.png"]);
+        assert_eq!(srcset_urls("a.png, b.png,"), ["a.png", "b.png"]);
+    }
+
+    #[test]
+    fn redirected_page_base() {
+        let html = b"<img src=\"logo.png\">";
+        let (page_url, _) = serve(vec![(
+            Duration::ZERO,
+            response("200 OK", "text/html", html),
+        )]);
+        let final_url = page_url.replace("image.png", "articles/");
+        let redirect = format!(
+            "HTTP/1.1 301 Moved Permanently\r\nLocation: {final_url}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        let (url, _) = serve(vec![(Duration::ZERO, redirect.into_bytes())]);
+        let images_urls = download_page_images_urls(&url, &DownloadOptions::default()).unwrap();
+        assert_eq!(images_urls, [format!("{final_url}logo.png")]);
+    }
+}
@@ -0,0 +1,58 @@
+use color_thief::Color;
+use serde_json::{json, Value};
+
+use crate::ColorTrait;
+
+/// Extracted colors of an image
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Palette {
+    pub colors: Vec<Color>,
+    /// Detected background colors, excluded from the colors
+    pub background: Vec<Color>,
+    /// Shares of the sampled pixels closest to each color
+    pub proportions: Vec<f32>,
+    /// Number of sampled pixels
+    pub pixels: u64,
+}
+
+impl Palette {
+    /// Palette as a JSON object of hexadecimal colors, with their proportions
+    pub fn to_json(&self) -> Value {
+        let hex_colors = |colors: &[Color]| -> Vec<String> {
+            colors.iter().map(|c| c.hexadecimal_str()).collect()
+        };
+        let proportions = self
+            .proportions
+            .iter()
+            .map(|proportion| (*proportion as f64 * 10000.0).round() / 10000.0)
+            .collect::<Vec<f64>>();
+        json!({
+            "colors": hex_colors(&self.colors),
+            "background": hex_colors(&self.background),
+            "proportions": proportions,
+            "pixels": self.pixels,
+        })
+    }
+
+    /// Palette of a JSON object of hexadecimal colors, None if it is invalid
+    pub fn from_json(palette_json: &Value) -> Option<Self> {
+        let json_colors = |key: &str| -> Option<Vec<Color>> {
+            palette_json[key]
+                .as_array()?
+                .iter()
+                .map(|hex| Color::hex_to_rgb(hex.as_str()?).ok())
+                .collect()
+        };
+        let proportions = palette_json["proportions"]
+            .as_array()?
+            .iter()
+            .map(|proportion| Some(proportion.as_f64()? as f32))
+            .collect::<Option<Vec<f32>>>()?;
+        Some(Palette {
+            colors: json_colors("colors")?,
+            background: json_colors("background")?,
+            proportions,
+            pixels: palette_json["pixels"].as_u64()?,
+        })
+    }
+}
@@ -0,0 +1,228 @@
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    env,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::http_cache::{used_files, write_atomically, EVICTION_TARGET_PERCENT};
+use crate::Palette;
+
+const ENTRY_EXTENSION: &str = "json";
+pub const DEFAULT_PALETTE_CACHE_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Identity of an image in the palettes cache
+pub struct CacheKey {
+    id: String,
+    /// Local file, with its modification time and size
+    source: Option<(String, u64, u64)>,
+}
+
+impl CacheKey {
+    /// Key of a local file, from its path, modification time and size
+    pub fn local_file(file_path: &str) -> Option<Self> {
+        let path = fs::canonicalize(file_path).ok()?.display().to_string();
+        let (modified, size) = file_state(Path::new(&path))?;
+        Some(CacheKey {
+            id: format!("{path}|{modified}|{size}"),
+            source: Some((path, modified, size)),
+        })
+    }
+
+    /// Key of an encoded image, from its content hash
+    pub fn content(data: &[u8]) -> Self {
+        CacheKey {
+            id: hex_digest(data),
+            source: None,
+        }
+    }
+}
+
+/// Extracted palettes cache, keyed by image and extraction options
+/// The least recently used palettes are evicted above the size cap.
+#[derive(Debug, Clone)]
+pub struct PaletteCache {
+    dir: PathBuf,
+    /// Extract again and replace the cached palettes
+    refresh: bool,
+    /// Maximum size of the cached palettes, in bytes
+    max_size: u64,
+    /// Size of the cached palettes, read from the directory once then tracked
+    size: Arc<Mutex<Option<u64>>>,
+}
+
+impl PaletteCache {
+    pub fn new(dir: PathBuf, refresh: bool, max_size: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(PaletteCache {
+            dir,
+            refresh,
+            max_size,
+            size: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    /// User cache directory of copycolors
+    pub fn default_dir() -> Option<PathBuf> {
+        let cache_home = env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+        Some(cache_home.join("copycolors"))
+    }
+
+    /// Entry path of an image extracted with the options of that cache key
+    fn entry_path(&self, key: &CacheKey, options_key: &str) -> PathBuf {
+        let entry_id = format!("{}|{options_key}", key.id);
+        self.dir
+            .join(hex_digest(entry_id.as_bytes()))
+            .with_extension(ENTRY_EXTENSION)
+    }
+
+    /// Cached palette of the image, marked as recently used
+    pub fn get(&self, key: &CacheKey, options_key: &str) -> Option<Palette> {
+        if self.refresh {
+            return None;
+        }
+        let entry_path = self.entry_path(key, options_key);
+        let entry: Value = serde_json::from_str(&fs::read_to_string(&entry_path).ok()?).ok()?;
+        let palette = Palette::from_json(&entry["palette"])?;
+        if let Ok(entry_file) = File::options().write(true).open(&entry_path) {
+            let _ = entry_file.set_modified(SystemTime::now());
+        }
+        Some(palette)
+    }
+
+    /// Cache the palette, then evict the least recently used palettes above the cap
+    pub fn put(&self, key: &CacheKey, options_key: &str, palette: &Palette) -> io::Result<()> {
+        let entry = match &key.source {
+            Some((path, modified, size)) => json!({
+                "source": path,
+                "modified": modified,
+                "size": size,
+                "palette": palette.to_json(),
+            }),
+            None => json!({ "palette": palette.to_json() }),
+        };
+        let entry_path = self.entry_path(key, options_key);
+        let replaced_size = fs::metadata(&entry_path).map_or(0, |metadata| metadata.len());
+        let entry = entry.to_string();
+        write_atomically(&entry_path, entry.as_bytes())?;
+        // The directory is only scanned again when the cap is exceeded
+        let mut size = self.size.lock().unwrap();
+        let cache_size = match *size {
+            Some(cache_size) => cache_size.saturating_sub(replaced_size) + entry.len() as u64,
+            None => used_files(&self.dir, ENTRY_EXTENSION)?
+                .iter()
+                .map(|(_, size, _)| size)
+                .sum(),
+        };
+        *size = Some(if cache_size > self.max_size {
+            self.evict()?
+        } else {
+            cache_size
+        });
+        Ok(())
+    }
+
+    /// Evict the least recently used palettes below the cap, and return the cache size
+    fn evict(&self) -> io::Result<u64> {
+        let mut entries = used_files(&self.dir, ENTRY_EXTENSION)?;
+        let mut cache_size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort();
+        for (_, size, entry_path) in entries {
+            if cache_size <= self.max_size / 100 * EVICTION_TARGET_PERCENT {
+                break;
+            }
+            // Entries can be evicted by parallel extractions
+            let _ = fs::remove_file(&entry_path);
+            cache_size -= size;
+        }
+        Ok(cache_size)
+    }
+}
+
+/// Remove the cached palettes unused for the maximum age, or of local files
+/// modified or deleted since, then return the number of removed palettes and their size
+pub fn prune_cache(dir: &Path, max_age: Duration) -> io::Result<(usize, u64)> {
+    let (mut removed, mut freed) = (0, 0);
+    let now = SystemTime::now();
+    for dir_entry in fs::read_dir(dir)?.filter_map(Result::ok) {
+        let path = dir_entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(ENTRY_EXTENSION) {
+            continue;
+        }
+        let Ok(metadata) = dir_entry.metadata() else {
+            continue;
+        };
+        let is_old = metadata
+            .modified()
+            .ok()
+            .and_then(|used| now.duration_since(used).ok())
+            .is_some_and(|age| age > max_age);
+        let entry = fs::read_to_string(&path)
+            .ok()
+            .and_then(|entry| serde_json::from_str::<Value>(&entry).ok());
+        let is_stale = match entry {
+            Some(entry) => match entry["source"].as_str() {
+                Some(source) => {
+                    file_state(Path::new(source))
+                        != entry["modified"].as_u64().zip(entry["size"].as_u64())
+                }
+                None => false,
+            },
+            None => true,
+        };
+        if (is_old || is_stale) && fs::remove_file(&path).is_ok() {
+            removed += 1;
+            freed += metadata.len();
+        }
+    }
+    Ok((removed, freed))
+}
+
+/// Modification time (in nanoseconds) and size of a file
+pub fn file_state(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()?
+        .as_nanos() as u64;
+    Some((modified, metadata.len()))
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eviction() {
+        let dir = tempfile::tempdir().unwrap();
+        let palette = Palette::default();
+        let entry_size = json!({ "palette": palette.to_json() }).to_string().len() as u64;
+        let cache = PaletteCache::new(dir.path().to_path_buf(), false, 10 * entry_size).unwrap();
+        for index in 0..20 {
+            let key = CacheKey::content(&[index]);
+            cache.put(&key, "options", &palette).unwrap();
+            assert_eq!(cache.get(&key, "options"), Some(palette.clone()));
+            assert_eq!(cache.get(&key, "other options"), None);
+        }
+        let nb_entries = used_files(dir.path(), ENTRY_EXTENSION).unwrap().len() as u64;
+        assert!(nb_entries <= 10);
+        assert_eq!(*cache.size.lock().unwrap(), Some(nb_entries * entry_size));
+    }
+}
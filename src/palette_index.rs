@@ -0,0 +1,602 @@
+use color_thief::Color;
+use rayon::prelude::*;
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, Connection, OptionalExtension};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+};
+
+use crate::images_batch::get_extracted_colors;
+use crate::near::{query_lab, weighted_distance, weighted_lab};
+use crate::palette_cache::file_state;
+use crate::{ColorTrait, ExtractionOptions, Palette};
+
+const INDEX_FILE_NAME: &str = "index.sqlite";
+// Images extracted between two commits, not to lose them on interruption
+const INDEX_BATCH_SIZE: usize = 256;
+// Grayish colors have no meaningful hue
+const MIN_HUE_CHROMA: f32 = 0.15;
+
+const INDEX_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS images (
+    path TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    modified INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    width INTEGER,
+    height INTEGER,
+    colorfulness REAL,
+    palette TEXT,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS colors (
+    path TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    hex TEXT NOT NULL,
+    hue REAL NOT NULL,
+    chroma REAL NOT NULL,
+    lightness REAL NOT NULL,
+    proportion REAL NOT NULL,
+    lab_l REAL NOT NULL,
+    lab_a REAL NOT NULL,
+    lab_b REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS colors_path ON colors (path);
+CREATE INDEX IF NOT EXISTS colors_hue ON colors (hue);
+CREATE INDEX IF NOT EXISTS images_colorfulness ON images (colorfulness);
+";
+
+/// Database of the palettes of local images, with their metadata
+pub struct PaletteIndex {
+    path: PathBuf,
+    connection: Connection,
+}
+
+/// Numbers of images of an indexing
+#[derive(Debug, Default)]
+pub struct IndexSummary {
+    pub indexed: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub failed: usize,
+}
+
+/// Filters of the indexed images, the closest to the near colors first
+#[derive(Debug, Default)]
+pub struct IndexQuery {
+    pub near: Option<Vec<Color>>,
+    /// Hue range in degrees, wrapping around 360
+    pub hue: Option<(f32, f32)>,
+    /// Minimum proportion of the palette in the hue range
+    pub min_share: f32,
+    pub min_colorfulness: Option<f32>,
+    pub max_colorfulness: Option<f32>,
+    /// Glob pattern of the file names, case insensitive
+    pub name: Option<String>,
+    pub top: usize,
+}
+
+/// Indexed image matching a query
+#[derive(Debug)]
+pub struct IndexedImage {
+    pub path: String,
+    pub palette: Palette,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub size: u64,
+    pub colorfulness: f32,
+    /// Distance to the near colors
+    pub distance: Option<f32>,
+}
+
+impl IndexedImage {
+    /// Palette as a JSON object, with the image path and metadata
+    pub fn to_json(&self) -> Value {
+        let mut image_json = self.palette.to_json();
+        image_json["path"] = Value::from(self.path.as_str());
+        image_json["width"] = Value::from(self.width);
+        image_json["height"] = Value::from(self.height);
+        image_json["size"] = Value::from(self.size);
+        image_json["colorfulness"] = Value::from((self.colorfulness as f64 * 10.0).round() / 10.0);
+        if let Some(distance) = self.distance {
+            image_json["distance"] = Value::from((distance as f64 * 100.0).round() / 100.0);
+        }
+        image_json
+    }
+}
+
+impl PaletteIndex {
+    pub fn open(index_path: &Path) -> Result<Self, String> {
+        let open_error = || {
+            format!(
+                "Sorry, the index: \"{}\" could not be opened.",
+                index_path.display()
+            )
+        };
+        if let Some(parent) = index_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent).map_err(|_| open_error())?;
+        }
+        let connection = Connection::open(index_path).map_err(|_| open_error())?;
+        connection
+            .execute_batch(INDEX_SCHEMA)
+            .map_err(|error| format!("Invalid index: {error}."))?;
+        Ok(PaletteIndex {
+            path: index_path.to_path_buf(),
+            connection,
+        })
+    }
+
+    /// User data directory file of the index
+    pub fn default_path() -> Option<PathBuf> {
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+            })
+            .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+        Some(data_home.join("copycolors").join(INDEX_FILE_NAME))
+    }
+
+    /// Extract the new and modified images of the repertories, and drop the deleted ones
+    /// An index of other extraction settings is refused, not to mix the palettes.
+    pub fn update(
+        &mut self,
+        repertories: &[String],
+        images_paths: Vec<String>,
+        nb_colors: u8,
+        options: &ExtractionOptions,
+    ) -> Result<IndexSummary, String> {
+        let sql_error =
+            |error: rusqlite::Error| format!("Error while updating the index: {error}.");
+        let extraction_key = options.settings_key(nb_colors);
+        let indexed_key = self
+            .connection
+            .query_row(
+                "SELECT value FROM settings WHERE key = 'extraction'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map_err(sql_error)?;
+        match indexed_key {
+            Some(indexed_key) if indexed_key != extraction_key => {
+                // Keys start with the version and the number of colors
+                let mut indexed_settings = indexed_key.split('|');
+                let version = indexed_settings.next().unwrap_or_default();
+                let indexed_nb_colors = indexed_settings.next().unwrap_or_default();
+                return Err(format!(
+                    "The index: \"{}\" stores palettes of other extraction settings: {indexed_nb_colors} colors, copycolors {version}.\nPlease, index with the same settings, or remove the index to extract the images again.",
+                    self.path.display()
+                ));
+            }
+            Some(_) => {}
+            None => {
+                self.connection
+                    .execute(
+                        "INSERT INTO settings (key, value) VALUES ('extraction', ?1)",
+                        params![extraction_key],
+                    )
+                    .map_err(sql_error)?;
+            }
+        }
+
+        let mut indexed_states = HashMap::new();
+        {
+            let mut statement = self
+                .connection
+                .prepare("SELECT path, modified, size FROM images")
+                .map_err(sql_error)?;
+            let rows = statement
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, (row.get(1)?, row.get(2)?)))
+                })
+                .map_err(sql_error)?;
+            for row in rows {
+                let (path, state): (String, (i64, i64)) = row.map_err(sql_error)?;
+                indexed_states.insert(path, (state.0 as u64, state.1 as u64));
+            }
+        }
+
+        let mut summary = IndexSummary::default();
+        // Paths are indexed absolute, to be queried from anywhere
+        let mut current_paths = HashSet::new();
+        let mut changed_images = vec![];
+        for path in images_paths {
+            let Ok(path) = fs::canonicalize(&path) else {
+                continue;
+            };
+            let path = path.display().to_string();
+            let Some(state) = file_state(Path::new(&path)) else {
+                continue;
+            };
+            if !current_paths.insert(path.clone()) {
+                continue;
+            }
+            if indexed_states.get(&path) == Some(&state) {
+                summary.unchanged += 1;
+            } else {
+                changed_images.push((path, state));
+            }
+        }
+
+        let repertories = repertories
+            .iter()
+            .filter_map(|repertory| fs::canonicalize(repertory).ok())
+            .collect::<Vec<PathBuf>>();
+        let removed_paths = indexed_states
+            .keys()
+            .filter(|path| !current_paths.contains(*path))
+            .filter(|path| {
+                repertories
+                    .iter()
+                    .any(|repertory| Path::new(path).starts_with(repertory))
+            })
+            .collect::<Vec<&String>>();
+        let transaction = self.connection.transaction().map_err(sql_error)?;
+        for path in &removed_paths {
+            transaction
+                .execute("DELETE FROM images WHERE path = ?1", params![path])
+                .map_err(sql_error)?;
+            transaction
+                .execute("DELETE FROM colors WHERE path = ?1", params![path])
+                .map_err(sql_error)?;
+        }
+        transaction.commit().map_err(sql_error)?;
+        summary.removed = removed_paths.len();
+
+        let nb_changed = changed_images.len();
+        let show_progress = io::stderr().is_terminal();
+        for batch in changed_images.chunks(INDEX_BATCH_SIZE) {
+            let extracted_images = batch
+                .par_iter()
+                .map(|(path, state)| {
+                    let dimensions = image::image_dimensions(path).ok();
+                    let colors_result = get_extracted_colors(path.clone(), nb_colors, options);
+                    (path, state, dimensions, colors_result)
+                })
+                .collect::<Vec<_>>();
+            let transaction = self.connection.transaction().map_err(sql_error)?;
+            for (path, (modified, size), dimensions, colors_result) in extracted_images {
+                let name = Path::new(path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let (palette_json, colorfulness, error) = match &colors_result {
+                    Ok(palette) => (
+                        Some(palette.to_json().to_string()),
+                        Some(palette_colorfulness(palette)),
+                        None,
+                    ),
+                    Err(error_message) => (None, None, Some(error_message.clone())),
+                };
+                transaction
+                    .execute(
+                        "INSERT OR REPLACE INTO images
+                        (path, name, modified, size, width, height, colorfulness, palette, error)
+                        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                        params![
+                            path,
+                            name,
+                            *modified as i64,
+                            *size as i64,
+                            dimensions.map(|(width, _)| width),
+                            dimensions.map(|(_, height)| height),
+                            colorfulness,
+                            palette_json,
+                            error,
+                        ],
+                    )
+                    .map_err(sql_error)?;
+                transaction
+                    .execute("DELETE FROM colors WHERE path = ?1", params![path])
+                    .map_err(sql_error)?;
+                let Ok(palette) = colors_result else {
+                    summary.failed += 1;
+                    continue;
+                };
+                insert_colors(&transaction, path, &palette).map_err(sql_error)?;
+                summary.indexed += 1;
+            }
+            transaction.commit().map_err(sql_error)?;
+            if show_progress {
+                let nb_extracted = summary.indexed + summary.failed;
+                // The last count is kept above the summary
+                let line_end = if nb_extracted == nb_changed { "\n" } else { "" };
+                eprint!("\rIndexing the images: {nb_extracted}/{nb_changed}{line_end}");
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Indexed images matching the query, ordered by distance to the near colors or by path
+    pub fn query(&self, query: &IndexQuery) -> Result<Vec<IndexedImage>, String> {
+        let sql_error =
+            |error: rusqlite::Error| format!("Error while querying the index: {error}.");
+        let mut filter_sql = String::from("images.error IS NULL");
+        let mut sql_params: Vec<SqlValue> = vec![];
+        if let Some(name) = &query.name {
+            filter_sql.push_str(" AND lower(images.name) GLOB ?");
+            sql_params.push(SqlValue::Text(name.to_lowercase()));
+        }
+        if let Some(min_colorfulness) = query.min_colorfulness {
+            filter_sql.push_str(" AND images.colorfulness >= ?");
+            sql_params.push(SqlValue::Real(min_colorfulness as f64));
+        }
+        if let Some(max_colorfulness) = query.max_colorfulness {
+            filter_sql.push_str(" AND images.colorfulness <= ?");
+            sql_params.push(SqlValue::Real(max_colorfulness as f64));
+        }
+        if let Some((min_hue, max_hue)) = query.hue {
+            let hue_condition = if min_hue <= max_hue {
+                "hue BETWEEN ? AND ?"
+            } else {
+                "(hue >= ? OR hue <= ?)"
+            };
+            // Share of the palette in the hue range, summed over its colors
+            filter_sql.push_str(&format!(
+                " AND (SELECT COALESCE(SUM(proportion), 0) FROM colors AS hue_colors
+                WHERE hue_colors.path = images.path AND chroma >= ? AND {hue_condition}) >= ?"
+            ));
+            sql_params.extend([
+                SqlValue::Real(MIN_HUE_CHROMA as f64),
+                SqlValue::Real(min_hue as f64),
+                SqlValue::Real(max_hue as f64),
+                SqlValue::Real(query.min_share as f64),
+            ]);
+        }
+
+        let images_sql = "SELECT path, width, height, size, colorfulness, palette FROM images";
+        let Some(near) = &query.near else {
+            sql_params.push(SqlValue::Integer(query.top as i64));
+            return self.query_images(
+                &format!("{images_sql} WHERE {filter_sql} ORDER BY path LIMIT ?"),
+                sql_params,
+                None,
+            );
+        };
+        // The images are ranked from their indexed CIELAB colors,
+        // then only the closest ones are read
+        let near_lab = query_lab(near);
+        let mut statement = self
+            .connection
+            .prepare(&format!(
+                "SELECT colors.path, lab_l, lab_a, lab_b, proportion
+                FROM colors JOIN images ON images.path = colors.path
+                WHERE {filter_sql} ORDER BY colors.path, position"
+            ))
+            .map_err(sql_error)?;
+        let rows = statement
+            .query_map(params_from_iter(sql_params), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    [row.get::<_, f64>(1)?, row.get(2)?, row.get(3)?],
+                    row.get::<_, f64>(4)?,
+                ))
+            })
+            .map_err(sql_error)?;
+        let mut ranking: Vec<(String, f32)> = vec![];
+        let mut image_lab: Vec<([f32; 3], f32)> = vec![];
+        let mut rows = rows.peekable();
+        while let Some(row) = rows.next() {
+            let (path, lab, proportion) = row.map_err(sql_error)?;
+            image_lab.push((lab.map(|channel| channel as f32), proportion as f32));
+            let is_last_color = match rows.peek() {
+                Some(Ok((next_path, _, _))) => *next_path != path,
+                _ => true,
+            };
+            if is_last_color {
+                ranking.push((path, weighted_distance(&image_lab, &near_lab)));
+                image_lab.clear();
+            }
+        }
+        ranking.sort_by(|(_, distance1), (_, distance2)| distance1.total_cmp(distance2));
+        ranking.truncate(query.top);
+
+        let mut images = vec![];
+        for (path, distance) in ranking {
+            images.extend(self.query_images(
+                &format!("{images_sql} WHERE path = ?"),
+                vec![SqlValue::Text(path)],
+                Some(distance),
+            )?);
+        }
+        Ok(images)
+    }
+
+    /// Indexed images of a query, with their distance to the near colors
+    fn query_images(
+        &self,
+        sql: &str,
+        sql_params: Vec<SqlValue>,
+        distance: Option<f32>,
+    ) -> Result<Vec<IndexedImage>, String> {
+        let sql_error =
+            |error: rusqlite::Error| format!("Error while querying the index: {error}.");
+        let mut statement = self.connection.prepare_cached(sql).map_err(sql_error)?;
+        let rows = statement
+            .query_map(params_from_iter(sql_params), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<u32>>(1)?,
+                    row.get::<_, Option<u32>>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, Option<f64>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                ))
+            })
+            .map_err(sql_error)?;
+        let mut images = vec![];
+        for row in rows {
+            let (path, width, height, size, colorfulness, palette_json) = row.map_err(sql_error)?;
+            let Some(palette) = palette_json
+                .and_then(|palette_json| serde_json::from_str::<Value>(&palette_json).ok())
+                .and_then(|palette_json| Palette::from_json(&palette_json))
+            else {
+                continue;
+            };
+            images.push(IndexedImage {
+                path,
+                palette,
+                width,
+                height,
+                size: size as u64,
+                colorfulness: colorfulness.unwrap_or(0.0) as f32,
+                distance,
+            });
+        }
+        Ok(images)
+    }
+}
+
+/// Rows of the palette colors, with their hue, chroma, lightness and CIELAB values
+fn insert_colors(connection: &Connection, path: &str, palette: &Palette) -> rusqlite::Result<()> {
+    for (position, (color, (lab, proportion))) in
+        palette.colors.iter().zip(weighted_lab(palette)).enumerate()
+    {
+        let (hue, chroma, lightness) = hue_chroma_lightness(color);
+        connection.execute(
+            "INSERT INTO colors
+            (path, position, hex, hue, chroma, lightness, proportion, lab_l, lab_a, lab_b)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                path,
+                position as i64,
+                color.hexadecimal_str(),
+                hue,
+                chroma,
+                lightness,
+                proportion,
+                lab[0],
+                lab[1],
+                lab[2],
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Hasler and Süsstrunk colorfulness of a palette, weighted by the colors proportions
+/// 0 for grayscale images, above 100 for extremely colorful ones
+fn palette_colorfulness(palette: &Palette) -> f32 {
+    let weights = palette
+        .colors
+        .iter()
+        .enumerate()
+        .map(|(index, _)| {
+            palette
+                .proportions
+                .get(index)
+                .copied()
+                .unwrap_or(1.0 / palette.colors.len() as f32)
+        })
+        .collect::<Vec<f32>>();
+    let total_weight = weights.iter().sum::<f32>().max(f32::EPSILON);
+    let opponents = palette
+        .colors
+        .iter()
+        .map(|color| {
+            let (r, g, b) = (color.r as f32, color.g as f32, color.b as f32);
+            (r - g, 0.5 * (r + g) - b)
+        })
+        .collect::<Vec<(f32, f32)>>();
+    let mean = |channel: &dyn Fn(&(f32, f32)) -> f32| {
+        opponents
+            .iter()
+            .zip(&weights)
+            .map(|(opponent, weight)| weight * channel(opponent))
+            .sum::<f32>()
+            / total_weight
+    };
+    let (mean_rg, mean_yb) = (mean(&|(rg, _)| *rg), mean(&|(_, yb)| *yb));
+    let (variance_rg, variance_yb) = (
+        mean(&|(rg, _)| (rg - mean_rg).powi(2)),
+        mean(&|(_, yb)| (yb - mean_yb).powi(2)),
+    );
+    (variance_rg + variance_yb).sqrt() + 0.3 * (mean_rg.powi(2) + mean_yb.powi(2)).sqrt()
+}
+
+/// Hue (in degrees), chroma and lightness (between 0 and 1) of a color
+fn hue_chroma_lightness(color: &Color) -> (f32, f32, f32) {
+    let [hue, _, lightness] = color.to_hsl();
+    let channels = color.to_slice();
+    let (max, min) = (
+        channels.iter().max().unwrap(),
+        channels.iter().min().unwrap(),
+    );
+    (hue, (max - min) as f32 / 255.0, lightness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Index of an image palette of these colors and proportions
+    fn index_palette(index: &PaletteIndex, path: &str, colors: &[(Color, f32)]) {
+        let palette = Palette {
+            colors: colors.iter().map(|(color, _)| *color).collect(),
+            proportions: colors.iter().map(|(_, proportion)| *proportion).collect(),
+            pixels: 100,
+            ..Palette::default()
+        };
+        index
+            .connection
+            .execute(
+                "INSERT INTO images (path, name, modified, size, colorfulness, palette)
+                VALUES (?1, ?1, 0, 0, ?2, ?3)",
+                params![
+                    path,
+                    palette_colorfulness(&palette),
+                    palette.to_json().to_string()
+                ],
+            )
+            .unwrap();
+        insert_colors(&index.connection, path, &palette).unwrap();
+    }
+
+    #[test]
+    fn hue_share_sum() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = PaletteIndex::open(&dir.path().join(INDEX_FILE_NAME)).unwrap();
+        let color = |r, g, b| Color { r, g, b };
+        // Three 20% reds, each below the minimum share
+        index_palette(
+            &index,
+            "reds.png",
+            &[
+                (color(255, 0, 0), 0.2),
+                (color(230, 20, 10), 0.2),
+                (color(200, 10, 0), 0.2),
+                (color(0, 0, 255), 0.4),
+            ],
+        );
+        index_palette(
+            &index,
+            "blue.png",
+            &[(color(255, 0, 0), 0.3), (color(0, 0, 255), 0.7)],
+        );
+        let query = IndexQuery {
+            hue: Some((340.0, 20.0)),
+            min_share: 0.5,
+            top: 10,
+            ..IndexQuery::default()
+        };
+        let paths = index
+            .query(&query)
+            .unwrap()
+            .into_iter()
+            .map(|image| image.path)
+            .collect::<Vec<String>>();
+        assert_eq!(paths, ["reds.png"]);
+    }
+}
@@ -0,0 +1,796 @@
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::{
+    env,
+    error::Error,
+    fmt,
+    io::{self, Read},
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+use url::Url;
+
+use crate::http_cache::{CachedResponse, HttpCache};
+use crate::netrc::Netrc;
+use crate::svg::is_svg_data;
+use crate::ImageFile;
+
+// Redirections followed before giving up, as browsers do
+const MAX_REDIRECTS: u32 = 10;
+// First waiting delay before a retry, doubled after each retry up to the maximum
+const RETRY_BACKOFF_MS: u64 = 500;
+const MAX_RETRY_BACKOFF_MS: u64 = 30_000;
+
+pub const DEFAULT_CONNECT_TIMEOUT_S: u64 = 10;
+pub const DEFAULT_READ_TIMEOUT_S: u64 = 30;
+pub const DEFAULT_MAX_DOWNLOAD_SIZE: u64 = 50 * 1024 * 1024;
+pub const DEFAULT_RETRIES: u32 = 2;
+pub const MAX_RETRIES: u32 = 10;
+pub const DEFAULT_USER_AGENT: &str = concat!("copycolors/", env!("CARGO_PKG_VERSION"));
+
+/// Remote images downloading settings
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    /// Maximum size of a downloaded image, in bytes
+    pub max_size: u64,
+    /// Retries after a server error or a timeout
+    pub retries: u32,
+    /// Headers sent with every request
+    pub headers: Vec<(String, String)>,
+    pub user_agent: String,
+    /// Netrc file logins authenticating the requests
+    pub netrc: Option<Netrc>,
+    /// Cache of the downloaded images, revalidated with the server
+    pub cache: Option<HttpCache>,
+    /// Serve the images from the cache only
+    pub offline: bool,
+    /// Refuse the hosts of private, loopback or link-local addresses,
+    /// for the URLs of the API clients
+    pub public_only: bool,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_S),
+            read_timeout: Duration::from_secs(DEFAULT_READ_TIMEOUT_S),
+            max_size: DEFAULT_MAX_DOWNLOAD_SIZE,
+            retries: DEFAULT_RETRIES,
+            headers: vec![],
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            netrc: None,
+            cache: None,
+            offline: false,
+            public_only: false,
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// Headers and netrc login sent with the URL request, keying its cached response
+    fn credentials(&self, url: &str) -> String {
+        let mut credentials = self
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {value}\n", name.to_lowercase()))
+            .collect::<String>();
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from));
+        if let Some(login) = self
+            .netrc
+            .as_ref()
+            .zip(host)
+            .and_then(|(netrc, host)| netrc.login(&host).cloned())
+        {
+            credentials.push_str(&format!("netrc: {}:{}\n", login.login, login.password));
+        }
+        credentials
+    }
+}
+
+/// Cause of a failed download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadErrorKind {
+    InvalidUrl,
+    /// Invalid proxy URL or proxy connection failure
+    Proxy,
+    /// DNS lookup, connection or TLS failure
+    Connection,
+    Timeout,
+    /// HTTP error status code
+    Status(u16),
+    /// The image is larger than the maximum size, in bytes
+    TooLarge(u64),
+    Read,
+    /// The image is not cached in offline mode
+    NotCached,
+    /// The host is a private, loopback or link-local address
+    PrivateAddress,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadError {
+    pub url: String,
+    pub kind: DownloadErrorKind,
+}
+
+impl DownloadError {
+    fn new(url: &str, kind: DownloadErrorKind) -> Self {
+        DownloadError {
+            url: url.to_string(),
+            kind,
+        }
+    }
+
+    /// Whether the download can succeed when retried
+    fn is_transient(&self) -> bool {
+        match self.kind {
+            DownloadErrorKind::Timeout => true,
+            DownloadErrorKind::Status(code) => code >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for DownloadErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadErrorKind::InvalidUrl => write!(f, "invalid URL"),
+            DownloadErrorKind::Proxy => write!(f, "proxy error"),
+            DownloadErrorKind::Connection => write!(f, "connection failed"),
+            DownloadErrorKind::Timeout => write!(f, "timed out"),
+            DownloadErrorKind::Status(code) => write!(f, "HTTP status {code}"),
+            DownloadErrorKind::TooLarge(max_size) => {
+                write!(f, "larger than the {} limit", format_size(*max_size))
+            }
+            DownloadErrorKind::Read => write!(f, "read error"),
+            DownloadErrorKind::NotCached => write!(f, "not cached, offline mode"),
+            DownloadErrorKind::PrivateAddress => write!(f, "private address"),
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Error while downloading the file: \"{}\" ({}).",
+            self.url, self.kind
+        )
+    }
+}
+
+impl Error for DownloadError {}
+
+/// Downloaded remote file
+pub struct RemoteFile {
+    /// Full URL, with its query string
+    pub url: String,
+    /// URL after the redirections, base of the page relative links
+    pub final_url: String,
+    pub data: Vec<u8>,
+    /// Media type announced by the server, without parameters
+    pub content_type: Option<String>,
+}
+
+/// Whether a path is a remote HTTP(S) URL
+pub fn is_remote_url(path: &str) -> bool {
+    matches!(Url::parse(path), Ok(url) if url.scheme() == "http" || url.scheme() == "https")
+}
+
+/// Whether all the addresses of the URL host are public,
+/// neither private, loopback nor link-local
+pub fn is_public_url(url: &Url) -> bool {
+    let Some(port) = url.port_or_known_default() else {
+        return false;
+    };
+    let addresses = match url.host() {
+        Some(url::Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Some(url::Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        Some(url::Host::Domain(domain)) => match (domain, port).to_socket_addrs() {
+            Ok(addresses) => addresses.collect(),
+            Err(_) => return false,
+        },
+        None => return false,
+    };
+    !addresses.is_empty() && addresses.iter().all(|address| is_public_ip(address.ip()))
+}
+
+/// Whether an IP address is reachable from the internet
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            // 0.0.0.0/8 and the 100.64.0.0/10 shared address space
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || first == 0
+                || (first == 100 && second & 0xC0 == 64))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            // fc00::/7 unique local and fe80::/10 link-local addresses
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.segments()[0] & 0xFE00 == 0xFC00
+                    || ip.segments()[0] & 0xFFC0 == 0xFE80)
+            }
+        },
+    }
+}
+
+/// HTTP header of a "Name: value" argument
+/// The ${VARIABLE} in the value are replaced by the environment variables values.
+pub fn parse_header(header: &str) -> Result<(String, String), String> {
+    let invalid_header = || format!("{header} is not a valid header. Ex: 'Name: value'.");
+    let (name, value) = header.split_once(':').ok_or_else(invalid_header)?;
+    let name = name.trim();
+    let is_token = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+    if name.is_empty() || !name.chars().all(is_token) {
+        return Err(invalid_header());
+    }
+    Ok((name.to_string(), expand_env_vars(value.trim())?))
+}
+
+fn expand_env_vars(value: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + length];
+        let Ok(variable) = env::var(name) else {
+            return Err(format!("The environment variable {name} is not set."));
+        };
+        expanded.push_str(&rest[..start]);
+        expanded.push_str(&variable);
+        rest = &rest[start + length + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Proxy of the URL from the environment variables, unless NO_PROXY excludes its host
+fn env_proxy(url: &Url) -> Option<String> {
+    let env_var = |name: &str| {
+        env::var(name)
+            .or_else(|_| env::var(name.to_lowercase()))
+            .ok()
+            .filter(|value| !value.is_empty())
+    };
+    let host = url.host_str()?.to_lowercase();
+    let no_proxy = env_var("NO_PROXY").unwrap_or_default();
+    let is_excluded = no_proxy
+        .split(',')
+        .map(str::trim)
+        .any(|pattern| pattern == "*" || no_proxy_matches(pattern, &host));
+    if is_excluded {
+        return None;
+    }
+    match url.scheme() {
+        "https" => env_var("HTTPS_PROXY"),
+        _ => env_var("HTTP_PROXY"),
+    }
+    .or_else(|| env_var("ALL_PROXY"))
+}
+
+/// Whether a NO_PROXY entry excludes a host, IPv6 addresses being bracketed like in URLs
+/// Ports are ignored, a domain also excludes its subdomains.
+fn no_proxy_matches(pattern: &str, host: &str) -> bool {
+    let domain = if let Some(address) = pattern.strip_prefix('[') {
+        // [IPv6] or [IPv6]:port
+        match address.split_once(']') {
+            Some((address, _)) => format!("[{address}]"),
+            None => return false,
+        }
+    } else if pattern.matches(':').count() > 1 {
+        // Bare IPv6 address
+        format!("[{pattern}]")
+    } else {
+        pattern
+            .split_once(':')
+            .map_or(pattern, |(domain, _)| domain)
+            .trim_start_matches('.')
+            .to_string()
+    };
+    let domain = domain.to_lowercase();
+    !domain.is_empty() && (host == domain || host.ends_with(&format!(".{domain}")))
+}
+
+/// Size in bytes, with an optional K, M or G (binary) unit
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let invalid_size = || format!("{size} is not a valid size. Ex: 500K, 20M, 1G.");
+    let upper_size = size.trim().to_uppercase();
+    let upper_size = upper_size.strip_suffix('B').unwrap_or(&upper_size);
+    let (number, unit) = match upper_size.char_indices().last() {
+        Some((i, 'K')) => (&upper_size[..i], 1 << 10),
+        Some((i, 'M')) => (&upper_size[..i], 1 << 20),
+        Some((i, 'G')) => (&upper_size[..i], 1 << 30),
+        _ => (upper_size, 1),
+    };
+    match number.trim().parse::<u64>() {
+        Ok(number) if number > 0 => number.checked_mul(unit).ok_or_else(invalid_size),
+        _ => Err(invalid_size()),
+    }
+}
+
+/// Size in bytes, with the largest unit dividing it
+pub fn format_size(size: u64) -> String {
+    match size {
+        s if s >= 1 << 30 && s % (1 << 30) == 0 => format!("{} GB", s >> 30),
+        s if s >= 1 << 20 && s % (1 << 20) == 0 => format!("{} MB", s >> 20),
+        s if s >= 1 << 10 && s % (1 << 10) == 0 => format!("{} KB", s >> 10),
+        s => format!("{s} bytes"),
+    }
+}
+
+/// Download a remote file, following the redirections
+/// Server errors and timeouts are retried with an exponential backoff.
+/// A cached file is only downloaded again if it changed.
+pub fn download_file(url: &str, options: &DownloadOptions) -> Result<RemoteFile, DownloadError> {
+    let credentials = options.credentials(url);
+    let cached = options
+        .cache
+        .as_ref()
+        .and_then(|cache| cache.get(url, &credentials));
+    if options.offline {
+        return match cached {
+            Some(cached) => Ok(RemoteFile::from_cache(url, cached)),
+            None => Err(DownloadError::new(url, DownloadErrorKind::NotCached)),
+        };
+    }
+    let mut attempt = 0;
+    loop {
+        match try_download_file(url, options, cached.as_ref(), &credentials) {
+            Err(error) if error.is_transient() && attempt < options.retries => {
+                thread::sleep(retry_backoff(attempt));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Waiting delay before the retry following that attempt
+fn retry_backoff(attempt: u32) -> Duration {
+    let backoff = RETRY_BACKOFF_MS.saturating_mul(1 << attempt.min(6));
+    Duration::from_millis(backoff.min(MAX_RETRY_BACKOFF_MS))
+}
+
+fn try_download_file(
+    url: &str,
+    options: &DownloadOptions,
+    cached: Option<&CachedResponse>,
+    credentials: &str,
+) -> Result<RemoteFile, DownloadError> {
+    let invalid_url = || DownloadError::new(url, DownloadErrorKind::InvalidUrl);
+    let mut request_url = Url::parse(url).map_err(|_| invalid_url())?;
+    // The redirections are followed here, as the custom headers
+    // are only sent to the origin of the requested URL
+    let origin = request_url.origin();
+    let mut nb_redirects = 0;
+    let response = loop {
+        if options.public_only && !is_public_url(&request_url) {
+            return Err(DownloadError::new(url, DownloadErrorKind::PrivateAddress));
+        }
+        let response = send_request(
+            &request_url,
+            options,
+            cached,
+            request_url.origin() == origin,
+        )
+        .map_err(|kind| DownloadError::new(url, kind))?;
+        let location = match response.status() {
+            301 | 302 | 303 | 307 | 308 => response.header("Location"),
+            _ => None,
+        };
+        let Some(location) = location else {
+            break response;
+        };
+        if nb_redirects == MAX_REDIRECTS {
+            return Err(DownloadError::new(
+                url,
+                DownloadErrorKind::Status(response.status()),
+            ));
+        }
+        request_url = request_url.join(location).map_err(|_| invalid_url())?;
+        if !matches!(request_url.scheme(), "http" | "https") {
+            return Err(invalid_url());
+        }
+        nb_redirects += 1;
+    };
+    if let (304, Some(cached)) = (response.status(), cached) {
+        return Ok(RemoteFile {
+            final_url: request_url.to_string(),
+            ..RemoteFile::from_cache(url, cached.clone())
+        });
+    }
+    let too_large = || DownloadError::new(url, DownloadErrorKind::TooLarge(options.max_size));
+    let content_length = response
+        .header("Content-Length")
+        .and_then(|length| length.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > options.max_size) {
+        return Err(too_large());
+    }
+    // A missing header is reported as text/plain by ureq
+    let content_type = response
+        .header("Content-Type")
+        .map(|_| response.content_type().to_lowercase());
+    let etag = response.header("ETag").map(String::from);
+    let last_modified = response.header("Last-Modified").map(String::from);
+    // One more byte than allowed tells an oversized body without a length
+    let mut data = vec![];
+    response
+        .into_reader()
+        .take(options.max_size + 1)
+        .read_to_end(&mut data)
+        .map_err(|error| {
+            let kind = match error.kind() {
+                io::ErrorKind::TimedOut => DownloadErrorKind::Timeout,
+                _ => DownloadErrorKind::Read,
+            };
+            DownloadError::new(url, kind)
+        })?;
+    if data.len() as u64 > options.max_size {
+        return Err(too_large());
+    }
+    let response = CachedResponse {
+        data,
+        content_type,
+        etag,
+        last_modified,
+        final_url: (request_url.as_str() != url).then(|| request_url.to_string()),
+    };
+    if let Some(cache) = &options.cache {
+        // A failed caching does not fail the extraction
+        let _ = cache.put(url, credentials, &response);
+    }
+    Ok(RemoteFile::from_cache(url, response))
+}
+
+/// GET request of a URL, without following its redirection
+/// The custom headers and the netrc login are only sent to the requested origin.
+fn send_request(
+    url: &Url,
+    options: &DownloadOptions,
+    cached: Option<&CachedResponse>,
+    is_requested_origin: bool,
+) -> Result<ureq::Response, DownloadErrorKind> {
+    let mut agent_builder = ureq::AgentBuilder::new()
+        .redirects(0)
+        .timeout_connect(options.connect_timeout)
+        .timeout_read(options.read_timeout)
+        .user_agent(&options.user_agent);
+    if let Some(proxy) = env_proxy(url) {
+        let proxy = ureq::Proxy::new(proxy).map_err(|_| DownloadErrorKind::Proxy)?;
+        agent_builder = agent_builder.proxy(proxy);
+    } else if options.public_only {
+        // Checked again on connection, as the host can resolve to another address
+        agent_builder = agent_builder.resolver(|address: &str| {
+            let addresses = address
+                .to_socket_addrs()?
+                .filter(|address| is_public_ip(address.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addresses.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "private address",
+                ));
+            }
+            Ok(addresses)
+        });
+    }
+    let mut request = agent_builder.build().request_url("GET", url);
+    let headers = if is_requested_origin {
+        options.headers.as_slice()
+    } else {
+        &[]
+    };
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    // Conditional request, answered by 304 Not Modified if the image did not change
+    if let Some(etag) = cached.and_then(|cached| cached.etag.as_deref()) {
+        request = request.set("If-None-Match", etag);
+    }
+    if let Some(last_modified) = cached.and_then(|cached| cached.last_modified.as_deref()) {
+        request = request.set("If-Modified-Since", last_modified);
+    }
+    let has_authorization = headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("Authorization"));
+    let netrc_host_login = match (&options.netrc, url.host_str()) {
+        (Some(netrc), Some(host)) if is_requested_origin && !has_authorization => netrc.login(host),
+        _ => None,
+    };
+    if let Some(netrc_host_login) = netrc_host_login {
+        let credentials = format!("{}:{}", netrc_host_login.login, netrc_host_login.password);
+        request = request.set(
+            "Authorization",
+            &format!("Basic {}", BASE64.encode(credentials)),
+        );
+    }
+    request.call().map_err(|error| match error {
+        ureq::Error::Status(code, _) => DownloadErrorKind::Status(code),
+        ureq::Error::Transport(transport) => match transport.kind() {
+            ureq::ErrorKind::InvalidUrl | ureq::ErrorKind::UnknownScheme => {
+                DownloadErrorKind::InvalidUrl
+            }
+            ureq::ErrorKind::InvalidProxyUrl
+            | ureq::ErrorKind::ProxyConnect
+            | ureq::ErrorKind::ProxyUnauthorized => DownloadErrorKind::Proxy,
+            _ if is_timeout(&transport) => DownloadErrorKind::Timeout,
+            _ => DownloadErrorKind::Connection,
+        },
+    })
+}
+
+/// Whether a transport error is caused by a timed out IO
+fn is_timeout(transport: &ureq::Transport) -> bool {
+    let mut source = transport.source();
+    while let Some(error) = source {
+        if let Some(io_error) = error.downcast_ref::<io::Error>() {
+            if io_error.kind() == io::ErrorKind::TimedOut {
+                return true;
+            }
+        }
+        source = error.source();
+    }
+    false
+}
+
+impl RemoteFile {
+    fn from_cache(url: &str, cached: CachedResponse) -> Self {
+        RemoteFile {
+            url: url.to_string(),
+            final_url: cached.final_url.unwrap_or_else(|| url.to_string()),
+            data: cached.data,
+            content_type: cached.content_type,
+        }
+    }
+
+    /// Image of the downloaded data
+    /// The decoder is picked from the magic bytes, then from the content type.
+    pub fn into_image_file(self, svg_size: u32) -> Result<ImageFile, String> {
+        let is_image_data = is_svg_data(&self.data) || image::guess_format(&self.data).is_ok();
+        match self.content_type.as_deref() {
+            Some(content_type) if !is_image_data && !content_type.starts_with("image/") => Err(
+                format!("The remote file is not an image (content type: {content_type})."),
+            ),
+            content_type => Ok(ImageFile::from_data(
+                self.url,
+                self.data,
+                content_type,
+                svg_size,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::mpsc::{channel, Receiver},
+    };
+
+    // Smallest valid PNG, a single black pixel
+    const PNG_DATA: &[u8] = &[
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00, 0x00, 0x00, 0x00, 0x3A,
+        0x7E, 0x9B, 0x55, 0x00, 0x00, 0x00, 0x0A, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0x60,
+        0x00, 0x00, 0x00, 0x02, 0x00, 0x01, 0x48, 0xAF, 0xA4, 0x71, 0x00, 0x00, 0x00, 0x00, 0x49,
+        0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+    ];
+
+    /// Local HTTP server answering the successive connections with the responses,
+    /// after an optional delay, the URL of its image and its received requests heads
+    pub(crate) fn serve(responses: Vec<(Duration, Vec<u8>)>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/image.png", listener.local_addr().unwrap());
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for (delay, response) in responses {
+                let Ok((mut stream, _)) = listener.accept() else {
+                    return;
+                };
+                // The request head ends with an empty line
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                while reader
+                    .read_line(&mut head)
+                    .is_ok_and(|_| !head.ends_with("\r\n\r\n"))
+                {}
+                let _ = sender.send(head);
+                thread::sleep(delay);
+                let _ = stream.write_all(&response);
+            }
+        });
+        (url, receiver)
+    }
+
+    pub(crate) fn response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend(body);
+        response
+    }
+
+    fn options() -> DownloadOptions {
+        DownloadOptions {
+            read_timeout: Duration::from_millis(300),
+            retries: 0,
+            ..DownloadOptions::default()
+        }
+    }
+
+    #[test]
+    fn private_address() {
+        let (url, _) = serve(vec![]);
+        let options = DownloadOptions {
+            public_only: true,
+            ..options()
+        };
+        let error = download_file(&url, &options).err().unwrap();
+        assert_eq!(error.kind, DownloadErrorKind::PrivateAddress);
+        for private_url in [
+            "http://localhost/",
+            "http://10.0.0.1/",
+            "http://169.254.169.254/",
+            "http://[::1]/",
+            "http://[fd00::1]/",
+            "http://[::ffff:192.168.1.1]/",
+        ] {
+            assert!(!is_public_url(&Url::parse(private_url).unwrap()));
+        }
+        assert!(is_public_url(&Url::parse("http://93.184.215.14/").unwrap()));
+    }
+
+    #[test]
+    fn capped_backoff() {
+        assert_eq!(retry_backoff(0), Duration::from_millis(RETRY_BACKOFF_MS));
+        assert_eq!(
+            retry_backoff(1),
+            Duration::from_millis(2 * RETRY_BACKOFF_MS)
+        );
+        for attempt in [6, 20, 64, u32::MAX] {
+            assert_eq!(
+                retry_backoff(attempt),
+                Duration::from_millis(MAX_RETRY_BACKOFF_MS)
+            );
+        }
+    }
+
+    #[test]
+    fn timeout_error() {
+        let (url, _) = serve(vec![(
+            Duration::from_secs(2),
+            response("200 OK", "image/png", PNG_DATA),
+        )]);
+        let error = download_file(&url, &options()).err().unwrap();
+        assert_eq!(error.kind, DownloadErrorKind::Timeout);
+    }
+
+    #[test]
+    fn too_large_error() {
+        let options = DownloadOptions {
+            max_size: 16,
+            ..options()
+        };
+        let (url, _) = serve(vec![(
+            Duration::ZERO,
+            response("200 OK", "image/png", PNG_DATA),
+        )]);
+        let error = download_file(&url, &options).err().unwrap();
+        assert_eq!(error.kind, DownloadErrorKind::TooLarge(16));
+
+        // Without a length, the body is read up to the limit
+        let mut response = b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n".to_vec();
+        response.extend(PNG_DATA);
+        let (url, _) = serve(vec![(Duration::ZERO, response)]);
+        let error = download_file(&url, &options).err().unwrap();
+        assert_eq!(error.kind, DownloadErrorKind::TooLarge(16));
+    }
+
+    #[test]
+    fn server_error_retry() {
+        let (url, _) = serve(vec![
+            (
+                Duration::ZERO,
+                response("503 Service Unavailable", "text/plain", b""),
+            ),
+            (Duration::ZERO, response("200 OK", "image/png", PNG_DATA)),
+        ]);
+        let remote_file = download_file(
+            &url,
+            &DownloadOptions {
+                retries: 1,
+                ..options()
+            },
+        )
+        .unwrap();
+        assert_eq!(remote_file.data, PNG_DATA);
+
+        let (url, _) = serve(vec![(
+            Duration::ZERO,
+            response("503 Service Unavailable", "text/plain", b""),
+        )]);
+        let error = download_file(&url, &options()).err().unwrap();
+        assert_eq!(error.kind, DownloadErrorKind::Status(503));
+    }
+
+    #[test]
+    fn not_image_content_type() {
+        let (url, _) = serve(vec![(
+            Duration::ZERO,
+            response("200 OK", "text/html; charset=utf-8", b"<html></html>"),
+        )]);
+        let remote_file = download_file(&url, &options()).unwrap();
+        assert_eq!(remote_file.content_type.as_deref(), Some("text/html"));
+        assert!(remote_file.into_image_file(512).is_err());
+
+        // The magic bytes prevail over a wrong content type
+        let (url, _) = serve(vec![(
+            Duration::ZERO,
+            response("200 OK", "application/octet-stream", PNG_DATA),
+        )]);
+        let remote_file = download_file(&url, &options()).unwrap();
+        assert!(remote_file.into_image_file(512).is_ok());
+    }
+
+    #[test]
+    fn cross_origin_redirect() {
+        let (target_url, target_requests) = serve(vec![(
+            Duration::ZERO,
+            response("200 OK", "image/png", PNG_DATA),
+        )]);
+        let redirect = format!(
+            "HTTP/1.1 302 Found\r\nLocation: {target_url}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        let (url, requests) = serve(vec![(Duration::ZERO, redirect.into_bytes())]);
+        let options = DownloadOptions {
+            headers: vec![("X-Api-Key".to_string(), "secret".to_string())],
+            ..options()
+        };
+        let remote_file = download_file(&url, &options).unwrap();
+        assert_eq!(remote_file.data, PNG_DATA);
+        assert!(requests.recv().unwrap().contains("X-Api-Key: secret"));
+        // The other port is another origin
+        assert!(!target_requests.recv().unwrap().contains("X-Api-Key"));
+    }
+
+    #[test]
+    fn no_proxy_patterns() {
+        assert!(no_proxy_matches("example.com", "images.example.com"));
+        assert!(no_proxy_matches(".example.com:8080", "example.com"));
+        assert!(!no_proxy_matches("example.com", "badexample.com"));
+        assert!(no_proxy_matches("::1", "[::1]"));
+        assert!(no_proxy_matches("[::1]:8080", "[::1]"));
+        assert!(!no_proxy_matches("[::1]:8080", "[::2]"));
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("500K"), Ok(500 << 10));
+        assert_eq!(parse_size("20mb"), Ok(20 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("99999999999999G").is_err());
+        assert_eq!(format_size(DEFAULT_MAX_DOWNLOAD_SIZE), "50 MB");
+    }
+}
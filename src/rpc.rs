@@ -0,0 +1,361 @@
+use color_thief::Color;
+use rayon::ThreadPool;
+use serde_json::{json, Map, Value};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, Write},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use crate::export::{export_color, export_colors, ExportFormat};
+use crate::extraction_options::parameter_value;
+use crate::images_batch::get_extracted_colors;
+use crate::palette_cache::file_state;
+use crate::remote::is_remote_url;
+use crate::uri::{is_data_uri, path_label};
+use crate::{ColorTrait, ExtractionOptions, Palette};
+
+// Palettes kept in memory, beyond which they are forgotten
+const MAX_MEMORY_PALETTES: usize = 1024;
+
+// JSON-RPC 2.0 error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const EXTRACTION_ERROR: i64 = -32000;
+
+/// Failed call, with its JSON-RPC error code
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn invalid_params(message: impl Into<String>) -> Self {
+        RpcError::new(INVALID_PARAMS, message)
+    }
+}
+
+/// Long-lived JSON-RPC session, keeping the palettes of the local files in memory
+struct RpcSession {
+    options: ExtractionOptions,
+    palettes: Mutex<HashMap<String, Palette>>,
+}
+
+/// Answer the line-delimited JSON-RPC requests of the standard input, until it is closed
+/// The requests are handled concurrently, their responses written as soon as ready.
+pub fn run_rpc(options: ExtractionOptions) -> io::Result<()> {
+    let session = Arc::new(RpcSession {
+        options,
+        palettes: Mutex::new(HashMap::new()),
+    });
+    let workers_pool = rayon::ThreadPoolBuilder::new()
+        .build()
+        .map_err(io::Error::other)?;
+    serve_lines(session, io::stdin().lock(), io::stdout(), &workers_pool);
+    Ok(())
+}
+
+/// Handle the request lines in the workers pool, read on the calling thread so that
+/// a waiting input never holds a worker, and write their responses from one thread
+/// Returns once the input is closed and the pending requests answered.
+fn serve_lines(
+    session: Arc<RpcSession>,
+    input: impl BufRead,
+    mut output: impl Write + Send + 'static,
+    workers_pool: &ThreadPool,
+) {
+    let (responses, received_responses) = mpsc::channel::<Value>();
+    let writer = thread::spawn(move || {
+        for response in received_responses {
+            // The client may have left
+            let _ = writeln!(output, "{response}");
+            let _ = output.flush();
+        }
+    });
+    for line in input.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (session, responses) = (Arc::clone(&session), responses.clone());
+        workers_pool.spawn(move || {
+            if let Some(response) = session.handle_line(&line) {
+                let _ = responses.send(response);
+            }
+        });
+    }
+    // The writer ends when the last pending request drops its sender
+    drop(responses);
+    let _ = writer.join();
+}
+
+impl RpcSession {
+    /// Response of a request line, None for the notifications
+    fn handle_line(&self, line: &str) -> Option<Value> {
+        let Ok(request) = serde_json::from_str::<Value>(line) else {
+            return Some(error_response(
+                Value::Null,
+                RpcError::new(PARSE_ERROR, "Parse error."),
+            ));
+        };
+        let id = request.get("id").cloned();
+        let Some(method) = request["method"].as_str() else {
+            return Some(error_response(
+                id.unwrap_or(Value::Null),
+                RpcError::new(INVALID_REQUEST, "Invalid request."),
+            ));
+        };
+        let empty_params = Map::new();
+        let result = match &request["params"] {
+            Value::Object(params) => self.call(method, params),
+            Value::Null => self.call(method, &empty_params),
+            _ => Err(RpcError::invalid_params(
+                "The parameters should be an object.",
+            )),
+        };
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(rpc_error) => error_response(id, rpc_error),
+        })
+    }
+
+    fn call(&self, method: &str, params: &Map<String, Value>) -> Result<Value, RpcError> {
+        match method {
+            "extract" => {
+                let (path, palette) = self.extract(params)?;
+                let mut palette_json = palette.to_json();
+                palette_json["path"] = Value::from(path_label(&path));
+                Ok(palette_json)
+            }
+            "contrast" => {
+                let foreground = color_param(params, "foreground")?;
+                let background = color_param(params, "background")?;
+                let ratio = foreground.contrast_ratio(background);
+                Ok(json!({
+                    "ratio": (ratio as f64 * 100.0).round() / 100.0,
+                    "aa": ratio >= 4.5,
+                    "aa_large": ratio >= 3.0,
+                    "aaa": ratio >= 7.0,
+                    "aaa_large": ratio >= 4.5,
+                }))
+            }
+            "convert" => Ok(export_color(&color_param(params, "color")?)),
+            "export" => {
+                let format = match params.get("format").and_then(Value::as_str) {
+                    Some(format) => {
+                        ExportFormat::from_name(format).map_err(RpcError::invalid_params)?
+                    }
+                    None => return Err(RpcError::invalid_params("The format is missing.")),
+                };
+                let name = params
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .unwrap_or("palette");
+                let colors = match params.get("colors") {
+                    Some(Value::Array(colors)) => colors
+                        .iter()
+                        .map(|color| {
+                            parse_color(color).ok_or_else(|| {
+                                RpcError::invalid_params(format!("{color} is not a valid color."))
+                            })
+                        })
+                        .collect::<Result<Vec<Color>, RpcError>>()?,
+                    Some(_) => {
+                        return Err(RpcError::invalid_params("The colors should be an array."))
+                    }
+                    None => self.extract(params)?.1.colors,
+                };
+                Ok(json!({ "content": export_colors(&colors, name, format) }))
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("Method not found: {method}."),
+            )),
+        }
+    }
+
+    /// Path and palette of the image of the path parameter,
+    /// extracted with the other parameters as options
+    fn extract(&self, params: &Map<String, Value>) -> Result<(String, Palette), RpcError> {
+        let Some(path) = params.get("path").and_then(Value::as_str) else {
+            return Err(RpcError::invalid_params("The image path is missing."));
+        };
+        let parameters = params
+            .iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "path" | "format" | "name"))
+            .map(|(key, value)| (Cow::from(key.as_str()), Cow::from(parameter_value(value))));
+        let (nb_colors, options) = self
+            .options
+            .with_parameters(parameters)
+            .map_err(RpcError::invalid_params)?;
+        // Local files are kept until modified
+        let memory_key = (!is_remote_url(path) && !is_data_uri(path))
+            .then(|| {
+                let canonical_path = fs::canonicalize(path).ok()?;
+                let state = file_state(&canonical_path)?;
+                Some(format!(
+                    "{}|{state:?}|{}",
+                    canonical_path.display(),
+                    options.cache_key(nb_colors, path)
+                ))
+            })
+            .flatten();
+        if let Some(memory_key) = &memory_key {
+            if let Some(palette) = self.palettes.lock().unwrap().get(memory_key) {
+                return Ok((path.to_string(), palette.clone()));
+            }
+        }
+        let palette = get_extracted_colors(path.to_string(), nb_colors, &options)
+            .map_err(|error_message| RpcError::new(EXTRACTION_ERROR, error_message))?;
+        if let Some(memory_key) = memory_key {
+            let mut palettes = self.palettes.lock().unwrap();
+            if palettes.len() >= MAX_MEMORY_PALETTES {
+                palettes.clear();
+            }
+            palettes.insert(memory_key, palette.clone());
+        }
+        Ok((path.to_string(), palette))
+    }
+}
+
+fn error_response(id: Value, rpc_error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": rpc_error.code, "message": rpc_error.message },
+    })
+}
+
+fn color_param(params: &Map<String, Value>, name: &str) -> Result<Color, RpcError> {
+    match params.get(name) {
+        Some(color) => parse_color(color)
+            .ok_or_else(|| RpcError::invalid_params(format!("{color} is not a valid color."))),
+        None => Err(RpcError::invalid_params(format!(
+            "The {name} color is missing."
+        ))),
+    }
+}
+
+/// Color of a hexadecimal code (with or without #), of a "rgb(r, g, b)" string,
+/// or of a [r, g, b] array
+fn parse_color(color: &Value) -> Option<Color> {
+    let channels = match color {
+        Value::String(color) => {
+            let color = color.trim();
+            if let Some(channels) = color
+                .strip_prefix("rgb(")
+                .and_then(|channels| channels.strip_suffix(')'))
+            {
+                channels
+                    .split(',')
+                    .map(|channel| channel.trim().parse::<u8>().ok())
+                    .collect::<Option<Vec<u8>>>()?
+            } else {
+                return Color::hex_to_rgb(color).ok();
+            }
+        }
+        Value::Array(channels) => channels
+            .iter()
+            .map(|channel| {
+                channel
+                    .as_u64()
+                    .and_then(|channel| u8::try_from(channel).ok())
+            })
+            .collect::<Option<Vec<u8>>>()?,
+        _ => return None,
+    };
+    match channels.as_slice() {
+        [r, g, b] => Some(Color {
+            r: *r,
+            g: *g,
+            b: *b,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufReader, Read},
+        sync::mpsc::{Receiver, Sender},
+        time::Duration,
+    };
+
+    /// Input of the lines sent by the test, open until the sender is dropped
+    struct ChannelInput(Receiver<Vec<u8>>, Vec<u8>);
+
+    impl Read for ChannelInput {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.1.is_empty() {
+                match self.0.recv() {
+                    Ok(data) => self.1 = data,
+                    Err(_) => return Ok(0),
+                }
+            }
+            let length = buffer.len().min(self.1.len());
+            buffer[..length].copy_from_slice(&self.1[..length]);
+            self.1.drain(..length);
+            Ok(length)
+        }
+    }
+
+    /// Output forwarding the written responses to the test
+    struct ChannelOutput(Sender<Vec<u8>>);
+
+    impl Write for ChannelOutput {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            let _ = self.0.send(data.to_vec());
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn response_with_open_input() {
+        let (input, input_lines) = mpsc::channel();
+        let (output_data, output) = mpsc::channel();
+        let session = Arc::new(RpcSession {
+            options: ExtractionOptions::default(),
+            palettes: Mutex::new(HashMap::new()),
+        });
+        thread::spawn(move || {
+            let workers_pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(1)
+                .build()
+                .unwrap();
+            let input = BufReader::new(ChannelInput(input_lines, vec![]));
+            serve_lines(session, input, ChannelOutput(output_data), &workers_pool);
+        });
+        let request =
+            r##"{"jsonrpc": "2.0", "id": 1, "method": "convert", "params": {"color": "#1E2E45"}}"##;
+        input.send(format!("{request}\n").into_bytes()).unwrap();
+        let mut response = vec![];
+        while !response.ends_with(b"\n") {
+            response.extend(output.recv_timeout(Duration::from_secs(5)).unwrap());
+        }
+        let response = serde_json::from_slice::<Value>(&response).unwrap();
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["hex"], "#1E2E45");
+        drop(input);
+    }
+}
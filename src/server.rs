@@ -0,0 +1,266 @@
+use serde_json::{json, Value};
+use std::{
+    io::Read,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+use url::Url;
+
+use crate::image_file_lib::get_image_file_colors;
+use crate::images_batch::get_extracted_colors;
+use crate::remote::{is_public_url, is_remote_url};
+use crate::{ExtractionOptions, ImageFile, Palette};
+
+// Requests waiting for a worker, beyond which the server is busy
+const PENDING_REQUESTS_PER_WORKER: usize = 4;
+
+/// Settings of the HTTP API server
+#[derive(Debug, Clone)]
+pub struct ServerOptions {
+    pub host: String,
+    pub port: u16,
+    pub workers: usize,
+    /// Maximum size of the uploaded images
+    pub max_upload_size: u64,
+    /// Extract the image URLs of private, loopback or link-local hosts
+    pub allow_private_urls: bool,
+}
+
+/// Failed request, with its HTTP status
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// Serve the colors extraction over HTTP, until the process is stopped
+/// The requests are handled by a bounded pool of workers, the extraction
+/// options of the requests are applied over the default ones.
+pub fn serve(server_options: ServerOptions, options: ExtractionOptions) -> Result<(), String> {
+    let address = format!("{}:{}", server_options.host, server_options.port);
+    let server = Server::http(&address)
+        .map_err(|error| format!("Sorry, the server could not listen on {address}: {error}."))?;
+    let workers_pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(server_options.workers)
+        .build()
+        .map_err(|error| format!("Sorry, the workers could not be started: {error}."))?;
+    let max_pending = server_options.workers * PENDING_REQUESTS_PER_WORKER;
+    let pending = Arc::new(AtomicUsize::new(0));
+    let server_options = Arc::new(server_options);
+    let options = Arc::new(options);
+    eprintln!("Listening on http://{address}");
+    for request in server.incoming_requests() {
+        if pending.load(Ordering::SeqCst) >= max_pending {
+            respond(
+                request,
+                Err(ApiError::new(
+                    503,
+                    "The server is busy, please retry later.",
+                )),
+            );
+            continue;
+        }
+        pending.fetch_add(1, Ordering::SeqCst);
+        let (pending, server_options, options) = (
+            Arc::clone(&pending),
+            Arc::clone(&server_options),
+            Arc::clone(&options),
+        );
+        workers_pool.spawn(move || {
+            handle_request(request, &server_options, &options);
+            pending.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+fn handle_request(
+    mut request: Request,
+    server_options: &ServerOptions,
+    options: &ExtractionOptions,
+) {
+    // Only the path and the query of the request URL are received
+    let Ok(url) = Url::parse(&format!("http://localhost{}", request.url())) else {
+        respond(request, Err(ApiError::new(400, "Invalid request URL.")));
+        return;
+    };
+    let result = match (request.method(), url.path()) {
+        (Method::Get, "/health") => Ok(json!({
+            "status": "ok",
+            "version": env!("CARGO_PKG_VERSION"),
+        })),
+        (Method::Post, "/extract") => {
+            extract(&mut request, &url, server_options, options).map(|palette| palette.to_json())
+        }
+        (_, "/health" | "/extract") => Err(ApiError::new(405, "Method not allowed.")),
+        _ => Err(ApiError::new(404, "Not found.")),
+    };
+    respond(request, result);
+}
+
+/// Palette of the uploaded image, or of the image URL
+/// The URL is given by the url query parameter, or by the url field of a JSON body.
+/// It is downloaded without the headers nor the netrc logins of the server.
+fn extract(
+    request: &mut Request,
+    url: &Url,
+    server_options: &ServerOptions,
+    options: &ExtractionOptions,
+) -> Result<Palette, ApiError> {
+    let (nb_colors, mut options) = options
+        .with_parameters(url.query_pairs().filter(|(key, _)| key != "url"))
+        .map_err(|error_message| ApiError::new(400, error_message))?;
+    let content_type = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Content-Type"))
+        .map(|header| header.value.as_str().to_string())
+        .unwrap_or_default();
+    let mut body = vec![];
+    request
+        .as_reader()
+        .take(server_options.max_upload_size + 1)
+        .read_to_end(&mut body)
+        .map_err(|_| ApiError::new(400, "The request body could not be read."))?;
+    if body.len() as u64 > server_options.max_upload_size {
+        return Err(ApiError::new(413, "The uploaded image is too large."));
+    }
+
+    let query_image_url = url
+        .query_pairs()
+        .find(|(key, _)| key == "url")
+        .map(|(_, image_url)| image_url.to_string());
+    let image_url = match query_image_url {
+        Some(image_url) => Some(image_url),
+        None if content_type.starts_with("application/json") => {
+            let body_json = serde_json::from_slice::<Value>(&body)
+                .map_err(|_| ApiError::new(400, "Invalid JSON body."))?;
+            let image_url = body_json["url"]
+                .as_str()
+                .ok_or_else(|| ApiError::new(400, "The JSON body should have an url field."))?;
+            Some(image_url.to_string())
+        }
+        None => None,
+    };
+    if let Some(image_url) = image_url {
+        // Local files and internal services of the server are not exposed
+        if !is_remote_url(&image_url) {
+            return Err(ApiError::new(
+                400,
+                "Only http and https image URLs are extracted.",
+            ));
+        }
+        options.download.headers.clear();
+        options.download.netrc = None;
+        options.download.public_only = !server_options.allow_private_urls;
+        let is_allowed_host = server_options.allow_private_urls
+            || Url::parse(&image_url).is_ok_and(|image_url| is_public_url(&image_url));
+        if !is_allowed_host {
+            return Err(ApiError::new(
+                400,
+                "The image URLs of private, loopback or link-local hosts are not extracted.",
+            ));
+        }
+        return get_extracted_colors(image_url, nb_colors, &options)
+            .map_err(|error_message| ApiError::new(422, error_message));
+    }
+
+    let (image_data, media_type) = if content_type.starts_with("multipart/form-data") {
+        multipart_file(&body, &content_type)
+            .ok_or_else(|| ApiError::new(400, "No image file in the uploaded form."))?
+    } else {
+        (
+            body,
+            Some(content_type).filter(|media_type| !media_type.is_empty()),
+        )
+    };
+    if image_data.is_empty() {
+        return Err(ApiError::new(400, "No image uploaded, nor image URL."));
+    }
+    let image_file = ImageFile::from_data(
+        "upload".to_string(),
+        image_data,
+        media_type.as_deref(),
+        options.svg_size,
+    );
+    get_image_file_colors(&image_file, nb_colors, &options)
+        .map_err(|error_message| ApiError::new(422, error_message))
+}
+
+/// Content and media type of the first file of a multipart form
+fn multipart_file(body: &[u8], content_type: &str) -> Option<(Vec<u8>, Option<String>)> {
+    let boundary = content_type
+        .split(';')
+        .filter_map(|parameter| parameter.trim().strip_prefix("boundary="))
+        .next()?
+        .trim_matches('"');
+    let delimiter = format!("--{boundary}");
+    let mut parts = split_bytes(body, delimiter.as_bytes());
+    parts.find_map(|part| {
+        let part = part.strip_prefix(b"\r\n")?;
+        let headers_end = part.windows(4).position(|window| window == b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&part[..headers_end]).to_string();
+        if !headers.contains("filename=") {
+            return None;
+        }
+        let content = &part[headers_end + 4..];
+        let content = content.strip_suffix(b"\r\n").unwrap_or(content);
+        let media_type = headers.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("Content-Type")
+                .then(|| value.trim().to_string())
+        });
+        Some((content.to_vec(), media_type))
+    })
+}
+
+/// Parts of the bytes between the delimiters
+fn split_bytes<'a>(bytes: &'a [u8], delimiter: &'a [u8]) -> impl Iterator<Item = &'a [u8]> {
+    let mut rest = bytes;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        match rest
+            .windows(delimiter.len())
+            .position(|window| window == delimiter)
+        {
+            Some(position) => {
+                let part = &rest[..position];
+                rest = &rest[position + delimiter.len()..];
+                Some(part)
+            }
+            None => {
+                let part = rest;
+                rest = &[];
+                Some(part)
+            }
+        }
+    })
+}
+
+/// JSON response of the result, errors are an object with an error field
+fn respond(request: Request, result: Result<Value, ApiError>) {
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err(api_error) => (api_error.status, json!({ "error": api_error.message })),
+    };
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    // The client may have left
+    let _ = request.respond(response);
+}
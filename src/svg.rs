@@ -0,0 +1,230 @@
+use color_thief::Color;
+use flate2::read::GzDecoder;
+use image::{
+    error::{DecodingError, ImageFormatHint},
+    DynamicImage, ImageError, ImageResult, RgbaImage,
+};
+use resvg::{
+    tiny_skia::{self, PathSegment, Point},
+    usvg::{self, Node, Paint},
+};
+use std::{
+    io::Read,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+/// Default size of the larger side of rasterized SVG images
+pub const DEFAULT_SVG_SIZE: u32 = 512;
+// Larger side of the rasterized SVG images, beyond which the pixmap is too large
+pub const MAX_SVG_SIZE: u32 = 8192;
+
+pub const SVG_MIME_TYPE: &str = "image/svg+xml";
+
+// Head of the documents looked through for the svg root element
+const SVG_SNIFFED_SIZE: usize = 64 * 1024;
+
+// System fonts are loaded once for all the SVG files
+static FONTS_DATABASE: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+
+pub fn is_svg_file(file_path: &str) -> bool {
+    matches!(
+        Path::new(file_path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .as_deref(),
+        Some("svg" | "svgz")
+    )
+}
+
+/// Whether encoded data is a SVG document, plain or gzipped
+pub fn is_svg_data(data: &[u8]) -> bool {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        // Only the head of a gzipped document is inflated
+        let mut head = vec![];
+        let _ = GzDecoder::new(data)
+            .take(SVG_SNIFFED_SIZE as u64)
+            .read_to_end(&mut head);
+        return is_svg_document(&head);
+    }
+    is_svg_document(data)
+}
+
+/// Whether the root element of a XML document is a svg one
+fn is_svg_document(data: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&data[..data.len().min(SVG_SNIFFED_SIZE)]);
+    let mut head = head.trim_start_matches('\u{feff}').trim_start();
+    // The root element can follow a XML declaration, a doctype and comments
+    loop {
+        let prolog_end = if head.starts_with("<?") {
+            head.find("?>").map(|end| end + 2)
+        } else if head.starts_with("<!--") {
+            head.find("-->").map(|end| end + 3)
+        } else if head.starts_with("<!") {
+            // The doctype can declare entities between brackets
+            match (head.find('['), head.find('>')) {
+                (Some(bracket), Some(end)) if bracket < end => head.find("]>").map(|end| end + 2),
+                (_, end) => end.map(|end| end + 1),
+            }
+        } else {
+            break;
+        };
+        let Some(prolog_end) = prolog_end else {
+            return false;
+        };
+        head = head[prolog_end..].trim_start();
+    }
+    head.starts_with("<svg")
+}
+
+/// SVG parsing options, with the system fonts
+fn svg_options() -> usvg::Options<'static> {
+    let fontdb = FONTS_DATABASE.get_or_init(|| {
+        #[cfg_attr(not(feature = "native"), allow(unused_mut))]
+        let mut fontdb = usvg::fontdb::Database::new();
+        // Without the native feature, there are no fonts to render the texts
+        #[cfg(feature = "native")]
+        fontdb.load_system_fonts();
+        Arc::new(fontdb)
+    });
+    usvg::Options {
+        fontdb: fontdb.clone(),
+        ..usvg::Options::default()
+    }
+}
+
+/// Rasterize a SVG document, its larger side scaled to the size
+pub fn rasterize_svg(svg_data: &[u8], size: u32) -> ImageResult<DynamicImage> {
+    let tree = usvg::Tree::from_data(svg_data, &svg_options()).map_err(svg_error)?;
+    let tree_size = tree.size();
+    let scale = size as f32 / tree_size.width().max(tree_size.height());
+    let width = (tree_size.width() * scale).round().max(1.0) as u32;
+    let height = (tree_size.height() * scale).round().max(1.0) as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| svg_error("invalid rasterization size"))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    // Pixmap colors are premultiplied by alpha
+    let rgba_bytes = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    Ok(DynamicImage::ImageRgba8(
+        RgbaImage::from_raw(width, height, rgba_bytes).unwrap(),
+    ))
+}
+
+/// Rasterize a SVG file
+pub fn open_svg(file_path: &str, size: u32) -> ImageResult<DynamicImage> {
+    let svg_data = std::fs::read(file_path).map_err(ImageError::IoError)?;
+    rasterize_svg(&svg_data, size)
+}
+
+fn svg_error<E: ToString>(error: E) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("SVG".to_string()),
+        error.to_string(),
+    ))
+}
+
+/// Colors painted in a SVG document (fills, strokes and gradients stops)
+/// with their approximate painted area, from the larger to the smaller
+pub fn svg_painted_colors(svg_data: &[u8]) -> ImageResult<Vec<(Color, f32)>> {
+    let tree = usvg::Tree::from_data(svg_data, &svg_options()).map_err(svg_error)?;
+    let mut painted_colors = Vec::<(Color, f32)>::new();
+    collect_painted_colors(tree.root(), &mut painted_colors);
+    painted_colors.sort_by(|(_, a1), (_, a2)| a2.total_cmp(a1));
+    Ok(painted_colors)
+}
+
+fn collect_painted_colors(group: &usvg::Group, painted_colors: &mut Vec<(Color, f32)>) {
+    for node in group.children() {
+        match node {
+            Node::Group(group) => collect_painted_colors(group, painted_colors),
+            Node::Text(text) => collect_painted_colors(text.flattened(), painted_colors),
+            Node::Path(path) if path.is_visible() => {
+                let (scale_x, scale_y) = path.abs_transform().get_scale();
+                if let Some(fill) = path.fill() {
+                    let bbox = path.abs_bounding_box();
+                    let area = bbox.width() * bbox.height() * fill.opacity().get();
+                    add_paint_colors(fill.paint(), area, painted_colors);
+                }
+                if let Some(stroke) = path.stroke() {
+                    let area = path_length(path.data())
+                        * (scale_x * scale_y).sqrt()
+                        * stroke.width().get()
+                        * stroke.opacity().get();
+                    add_paint_colors(stroke.paint(), area, painted_colors);
+                }
+            }
+            // Embedded raster images are not vector colors
+            _ => {}
+        }
+    }
+}
+
+/// Add the paint colors, a gradient area is shared between its stops
+fn add_paint_colors(paint: &Paint, area: f32, painted_colors: &mut Vec<(Color, f32)>) {
+    let stops = match paint {
+        Paint::Color(color) => {
+            add_painted_color(color, area, painted_colors);
+            return;
+        }
+        Paint::LinearGradient(gradient) => gradient.stops(),
+        Paint::RadialGradient(gradient) => gradient.stops(),
+        Paint::Pattern(pattern) => {
+            collect_painted_colors(pattern.root(), painted_colors);
+            return;
+        }
+    };
+    for stop in stops {
+        let stop_area = area * stop.opacity().get() / stops.len() as f32;
+        add_painted_color(&stop.color(), stop_area, painted_colors);
+    }
+}
+
+fn add_painted_color(color: &usvg::Color, area: f32, painted_colors: &mut Vec<(Color, f32)>) {
+    // Nothing is painted, as the fill of a straight line
+    if area <= 0.0 {
+        return;
+    }
+    let color = Color {
+        r: color.red,
+        g: color.green,
+        b: color.blue,
+    };
+    match painted_colors.iter_mut().find(|(c, _)| *c == color) {
+        Some((_, painted_area)) => *painted_area += area,
+        None => painted_colors.push((color, area)),
+    }
+}
+
+/// Path length, the curves are approximated by their chords
+fn path_length(path: &tiny_skia::Path) -> f32 {
+    let mut length = 0.0;
+    let (mut start, mut last) = (Point::zero(), Point::zero());
+    for segment in path.segments() {
+        let next = match segment {
+            PathSegment::MoveTo(point) => {
+                start = point;
+                last = point;
+                continue;
+            }
+            PathSegment::LineTo(point)
+            | PathSegment::QuadTo(_, point)
+            | PathSegment::CubicTo(_, _, point) => point,
+            PathSegment::Close => start,
+        };
+        length += last.distance(next);
+        last = next;
+    }
+    length
+}
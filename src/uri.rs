@@ -0,0 +1,99 @@
+use base64::{
+    alphabet,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    Engine,
+};
+use percent_encoding::percent_decode_str;
+use std::borrow::Cow;
+use url::Url;
+
+// Data URIs are often emitted without their base64 padding
+const BASE64_INDIFFERENT_PADDING: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Local path of a file:// URI
+pub fn file_uri_path(path: &str) -> Option<String> {
+    let url = Url::parse(path).ok().filter(|url| url.scheme() == "file")?;
+    let file_path = url.to_file_path().ok()?;
+    Some(file_path.display().to_string())
+}
+
+pub fn is_data_uri(path: &str) -> bool {
+    path.get(..5)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+}
+
+/// Label of an image path, data URIs being shortened to their media type and size
+pub fn path_label(path: &str) -> Cow<'_, str> {
+    if is_data_uri(path) {
+        Cow::Owned(data_uri_label(path))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// Short label of a data URI, e.g. "data:image/png (12 KB)",
+/// as the URI itself can be megabytes of base64
+fn data_uri_label(uri: &str) -> String {
+    let (header, data) = uri[5..].split_once(',').unwrap_or((&uri[5..], ""));
+    let mut parameters = header.split(';').map(str::trim);
+    let media_type = parameters
+        .next()
+        .filter(|media_type| !media_type.is_empty())
+        .unwrap_or("text/plain")
+        .to_lowercase();
+    let is_base64 = parameters.any(|parameter| parameter.eq_ignore_ascii_case("base64"));
+    // Estimated decoded size
+    let size = if is_base64 {
+        data.len() * 3 / 4
+    } else {
+        data.len()
+    };
+    let size = match size {
+        s if s >= 1 << 20 => format!("{:.1} MB", s as f32 / (1 << 20) as f32),
+        s if s >= 1 << 10 => format!("{} KB", (s as f32 / (1 << 10) as f32).round()),
+        s => format!("{s} bytes"),
+    };
+    format!("data:{media_type} ({size})")
+}
+
+/// Decoded data of a data: URI, with its media type if any
+pub fn decode_data_uri(uri: &str) -> Result<(Vec<u8>, Option<String>), String> {
+    let invalid_uri = || "Invalid data URI.".to_string();
+    let (header, data) = uri[5..].split_once(',').ok_or_else(invalid_uri)?;
+    let mut parameters = header.split(';').map(str::trim);
+    let media_type = parameters
+        .next()
+        .filter(|media_type| !media_type.is_empty())
+        .map(|media_type| media_type.to_lowercase());
+    let is_base64 = parameters.any(|parameter| parameter.eq_ignore_ascii_case("base64"));
+    // Base64 data can also be percent-encoded
+    let data = percent_decode_str(data).collect::<Vec<u8>>();
+    let data = if is_base64 {
+        let base64_data = data
+            .into_iter()
+            .filter(|byte| !byte.is_ascii_whitespace())
+            .collect::<Vec<u8>>();
+        BASE64_INDIFFERENT_PADDING
+            .decode(base64_data)
+            .map_err(|_| invalid_uri())?
+    } else {
+        data
+    };
+    Ok((data, media_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_uri_labels() {
+        let png_uri = format!("data:image/PNG;base64,{}", "A".repeat(16384));
+        assert_eq!(path_label(&png_uri), "data:image/png (12 KB)");
+        assert_eq!(path_label("data:,Hello"), "data:text/plain (5 bytes)");
+        assert_eq!(path_label("images/photo.png"), "images/photo.png");
+    }
+}
@@ -0,0 +1,132 @@
+use color_thief::Color;
+use serde_json::Value;
+use std::borrow::Cow;
+use wasm_bindgen::prelude::*;
+
+use crate::export::{export_color, export_colors, ExportFormat};
+use crate::extraction_options::parameter_value;
+use crate::image_file_lib::get_image_file_colors;
+use crate::{ColorTrait, ExtractionOptions, ImageFile};
+
+/// JSON palette of an encoded image, its format is guessed from its content
+/// or else from its media type
+/// The options are a JSON object named like the command line options,
+/// e.g. {"n": 6, "exc-colors": ["#FFFFFF"], "ignore-background": true}.
+#[wasm_bindgen(js_name = extractColors)]
+pub fn extract_colors(
+    image_data: Vec<u8>,
+    media_type: Option<String>,
+    options: Option<String>,
+) -> Result<String, JsError> {
+    let options_json = match options {
+        Some(options) => serde_json::from_str::<Value>(&options)
+            .map_err(|_| JsError::new("The options should be a JSON object."))?,
+        None => Value::Null,
+    };
+    let parameters = options_json
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| (Cow::from(key.as_str()), Cow::from(parameter_value(value))));
+    let (nb_colors, options) = ExtractionOptions::default()
+        .with_parameters(parameters)
+        .map_err(|error_message| JsError::new(&error_message))?;
+    let image_file = ImageFile::from_data(
+        "image".to_string(),
+        image_data,
+        media_type.as_deref(),
+        options.svg_size,
+    );
+    let palette = get_image_file_colors(&image_file, nb_colors, &options)
+        .map_err(|error_message| JsError::new(&error_message))?;
+    Ok(palette.to_json().to_string())
+}
+
+/// WCAG contrast ratio of two hexadecimal colors, from 1 to 21
+#[wasm_bindgen(js_name = contrastRatio)]
+pub fn contrast_ratio(foreground: &str, background: &str) -> Result<f32, JsError> {
+    Ok(parse_color(foreground)?.contrast_ratio(parse_color(background)?))
+}
+
+/// JSON hexadecimal, RGB, HSL and CIELAB values of a hexadecimal color
+#[wasm_bindgen(js_name = convertColor)]
+pub fn convert_color(color: &str) -> Result<String, JsError> {
+    Ok(export_color(&parse_color(color)?).to_string())
+}
+
+/// Palette file content of hexadecimal colors, in the gpl, css or json format
+#[wasm_bindgen(js_name = exportPalette)]
+pub fn export_palette(colors: Vec<String>, name: &str, format: &str) -> Result<String, JsError> {
+    let format = ExportFormat::from_name(format).map_err(|error| JsError::new(&error))?;
+    let colors = colors
+        .iter()
+        .map(|color| parse_color(color))
+        .collect::<Result<Vec<Color>, JsError>>()?;
+    Ok(export_colors(&colors, name, format))
+}
+
+/// Color of a hexadecimal code, with or without #
+fn parse_color(hex_code: &str) -> Result<Color, JsError> {
+    Color::hex_to_rgb(hex_code).map_err(|error_message| JsError::new(&error_message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+    use std::io::Cursor;
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    /// PNG image, red on its left half and blue on its right half
+    fn png_data() -> Vec<u8> {
+        let image = RgbImage::from_fn(16, 16, |x, _| {
+            if x < 8 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        let mut data = Cursor::new(vec![]);
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut data, ImageOutputFormat::Png)
+            .unwrap();
+        data.into_inner()
+    }
+
+    // The successful calls create no JavaScript values, so they also run natively
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn extract_colors_options() {
+        let palette = extract_colors(png_data(), None, Some(r#"{"n": 2}"#.to_string())).unwrap();
+        let palette = serde_json::from_str::<Value>(&palette).unwrap();
+        let mut colors = palette["colors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|color| color.as_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+        colors.sort();
+        // The quantization keeps 5 bits per channel
+        assert_eq!(colors, ["#0404FC", "#FC0404"]);
+    }
+
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen_test)]
+    #[cfg_attr(not(target_arch = "wasm32"), test)]
+    fn convert_color_codes() {
+        let color = serde_json::from_str::<Value>(&convert_color("1e2e45").unwrap()).unwrap();
+        assert_eq!(color["hex"], "#1E2E45");
+        assert_eq!(
+            convert_color("#1E2E45").unwrap(),
+            convert_color("1e2e45").unwrap()
+        );
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen_test]
+    fn invalid_inputs() {
+        assert!(convert_color("#1E2E4").is_err());
+        assert!(extract_colors(png_data(), None, Some(r#"{"unknown": 1}"#.to_string())).is_err());
+        assert!(extract_colors(vec![0; 16], None, None).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use glob::Pattern;
+use notify::{
+    event::{AccessKind, AccessMode, ModifyKind},
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use crate::IMAGES_EXTENSION_REGEX_PATTERN;
+
+// Exports write their files in several steps, which are extracted once settled
+const SETTLE_DELAY: Duration = Duration::from_millis(300);
+
+/// Watched directory, with the prefix of its images paths
+struct WatchedDir {
+    path: PathBuf,
+    prefix: PathBuf,
+    canonical_path: Option<PathBuf>,
+    recursive: bool,
+    /// New images of the directory join the collection
+    collects_images: bool,
+}
+
+/// Filesystem watcher of the images of a collection
+pub struct ImagesWatcher {
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    dirs: Vec<WatchedDir>,
+    patterns: Vec<Pattern>,
+    name_regex: Regex,
+    images_regex: Regex,
+    /// Changed images paths, with the time of their last event
+    pending: HashMap<String, Instant>,
+}
+
+impl ImagesWatcher {
+    /// Watch the repertories, glob patterns and files of a collection
+    pub fn new(
+        directories: &[String],
+        patterns: &[String],
+        files: &[String],
+        regex_pattern: &str,
+        recursive: bool,
+    ) -> Result<Self, String> {
+        let name_regex = Regex::new(regex_pattern)
+            .map_err(|_| format!("Invalid regex pattern: {regex_pattern}"))?;
+        let mut dirs = vec![];
+        for directory in directories {
+            dirs.push(WatchedDir {
+                path: PathBuf::from(directory),
+                canonical_path: fs::canonicalize(directory).ok(),
+                prefix: PathBuf::from(directory),
+                recursive,
+                collects_images: true,
+            });
+        }
+        let mut glob_patterns = vec![];
+        for pattern in patterns {
+            glob_patterns.push(
+                Pattern::new(pattern).map_err(|_| format!("Invalid glob pattern: {pattern}"))?,
+            );
+            // The literal part of the pattern is watched
+            let literal_prefix = Path::new(pattern)
+                .components()
+                .take_while(|component| {
+                    !component
+                        .as_os_str()
+                        .to_string_lossy()
+                        .contains(['*', '?', '['])
+                })
+                .collect::<PathBuf>();
+            dirs.push(watched_parent(literal_prefix, pattern.contains("**")));
+        }
+        for file in files {
+            let parent = Path::new(file).parent().unwrap_or(Path::new(""));
+            dirs.push(watched_parent(parent.to_path_buf(), false));
+        }
+
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)
+            .map_err(|error| format!("Error while watching the files: {error}."))?;
+        for dir in &dirs {
+            let mode = if dir.recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            watcher.watch(&dir.path, mode).map_err(|error| {
+                format!(
+                    "Error while watching the directory: \"{}\" ({error}).",
+                    dir.path.display()
+                )
+            })?;
+        }
+        Ok(ImagesWatcher {
+            _watcher: watcher,
+            events,
+            dirs,
+            patterns: glob_patterns,
+            name_regex,
+            images_regex: Regex::new(IMAGES_EXTENSION_REGEX_PATTERN).unwrap(),
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Paths of the images added, modified or removed, once their changes settled
+    pub fn changed_paths(&mut self) -> Vec<String> {
+        let now = Instant::now();
+        while let Ok(event) = self.events.try_recv() {
+            let Ok(event) = event else {
+                continue;
+            };
+            let is_change = match event.kind {
+                EventKind::Create(_) | EventKind::Remove(_) => true,
+                EventKind::Modify(ModifyKind::Metadata(_)) => false,
+                EventKind::Modify(_) => true,
+                EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+                _ => false,
+            };
+            if !is_change {
+                continue;
+            }
+            for path in event.paths {
+                if let Some(image_path) = self.image_path(&path) {
+                    self.pending.insert(image_path, now);
+                }
+            }
+        }
+        let mut settled_paths = self
+            .pending
+            .iter()
+            .filter(|(_, changed)| now.duration_since(**changed) >= SETTLE_DELAY)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<String>>();
+        for path in &settled_paths {
+            self.pending.remove(path);
+        }
+        settled_paths.sort();
+        settled_paths
+    }
+
+    /// Whether a new file belongs to the collection
+    pub fn is_new_image(&self, path: &str) -> bool {
+        let path = Path::new(path);
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            return false;
+        };
+        if !self.images_regex.is_match(file_name) {
+            return false;
+        }
+        let in_directory = self.dirs.iter().any(|dir| {
+            dir.collects_images
+                && path.strip_prefix(&dir.prefix).is_ok_and(|relative_path| {
+                    dir.recursive || relative_path.components().count() == 1
+                })
+        });
+        (in_directory && self.name_regex.is_match(file_name))
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| pattern.matches_path(path))
+    }
+
+    /// Path of a changed file, as the collection names it
+    fn image_path(&self, event_path: &Path) -> Option<String> {
+        self.dirs
+            .iter()
+            .filter_map(|dir| {
+                // Some platforms report canonical paths
+                let relative_path = event_path
+                    .strip_prefix(&dir.path)
+                    .ok()
+                    .or_else(|| event_path.strip_prefix(dir.canonical_path.as_ref()?).ok())?;
+                Some((
+                    dir.path.components().count(),
+                    dir.prefix.join(relative_path),
+                ))
+            })
+            .max_by_key(|(depth, _)| *depth)
+            .map(|(_, image_path)| image_path.display().to_string())
+    }
+}
+
+/// Directory of files, the current one being watched for an empty path
+fn watched_parent(path: PathBuf, recursive: bool) -> WatchedDir {
+    let watched_path = if path.as_os_str().is_empty() {
+        Component::CurDir.as_os_str().into()
+    } else {
+        path.clone()
+    };
+    WatchedDir {
+        canonical_path: fs::canonicalize(&watched_path).ok(),
+        path: watched_path,
+        prefix: path,
+        recursive,
+        collects_images: false,
+    }
+}
@@ -0,0 +1,187 @@
+use image::{imageops::FilterType, DynamicImage, ImageBuffer, Luma};
+use std::f32::consts::PI;
+
+// Saliency map side, the image is scaled down to it
+const SALIENCY_SIZE: u32 = 64;
+// Weight of the less important pixels, so they still count a little
+const MIN_WEIGHT: f32 = 0.1;
+
+type Complex = (f32, f32);
+
+/// Pixels weighting modes for the quantization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weighting {
+    /// Pixels near the image center count more
+    Center,
+    /// Pixels in salient regions (spectral residual) count more
+    Saliency,
+    /// Pixels of saturated colors count more
+    Saturation,
+}
+
+impl Weighting {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "center" => Ok(Weighting::Center),
+            "saliency" => Ok(Weighting::Saliency),
+            "saturation" => Ok(Weighting::Saturation),
+            _ => Err(format!(
+                "{name} is not a valid weighting mode. Possible values: center, saliency, saturation."
+            )),
+        }
+    }
+
+    /// Pixels weights (between 0 and 1) of the image
+    pub fn pixels_weights(&self, image: &DynamicImage) -> Vec<f32> {
+        match self {
+            Weighting::Center => center_weights(image.width(), image.height()),
+            Weighting::Saliency => saliency_weights(image),
+            Weighting::Saturation => saturation_weights(image),
+        }
+    }
+}
+
+/// Gaussian falloff from the image center
+fn center_weights(width: u32, height: u32) -> Vec<f32> {
+    let (half_w, half_h) = (width as f32 / 2.0, height as f32 / 2.0);
+    let sigma2 = 2.0 * 0.5_f32.powi(2);
+    let mut weights = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let dx = (x as f32 + 0.5 - half_w) / half_w;
+            let dy = (y as f32 + 0.5 - half_h) / half_h;
+            weights.push((-(dx * dx + dy * dy) / sigma2).exp().max(MIN_WEIGHT));
+        }
+    }
+    weights
+}
+
+/// Chroma of the pixels colors, the gray pixels counting the less
+fn saturation_weights(image: &DynamicImage) -> Vec<f32> {
+    image
+        .to_rgb8()
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.0;
+            let chroma = (r.max(g).max(b) - r.min(g).min(b)) as f32 / 255.0;
+            MIN_WEIGHT + (1.0 - MIN_WEIGHT) * chroma
+        })
+        .collect()
+}
+
+/// Spectral residual saliency map scaled to the image size
+/// https://doi.org/10.1109/CVPR.2007.383267
+fn saliency_weights(image: &DynamicImage) -> Vec<f32> {
+    let n = SALIENCY_SIZE as usize;
+    let gray = image
+        .resize_exact(SALIENCY_SIZE, SALIENCY_SIZE, FilterType::Triangle)
+        .to_luma32f();
+    let mut spectrum: Vec<Complex> = gray.pixels().map(|p| (p.0[0], 0.0)).collect();
+    dft_2d(&mut spectrum, n, false);
+
+    // Log amplitude spectrum minus its local average
+    let log_amplitude: Vec<f32> = spectrum
+        .iter()
+        .map(|(re, im)| (re.hypot(*im) + 1e-6).ln())
+        .collect();
+    let mut residual_spectrum = Vec::<Complex>::with_capacity(n * n);
+    for v in 0..n {
+        for u in 0..n {
+            let mut local_sum = 0.0;
+            for dv in [n - 1, 0, 1] {
+                for du in [n - 1, 0, 1] {
+                    local_sum += log_amplitude[((v + dv) % n) * n + (u + du) % n];
+                }
+            }
+            let residual = log_amplitude[v * n + u] - local_sum / 9.0;
+            let (re, im) = spectrum[v * n + u];
+            let phase = im.atan2(re);
+            let amplitude = residual.exp();
+            residual_spectrum.push((amplitude * phase.cos(), amplitude * phase.sin()));
+        }
+    }
+    dft_2d(&mut residual_spectrum, n, true);
+
+    let saliency: Vec<f32> = residual_spectrum
+        .iter()
+        .map(|(re, im)| re * re + im * im)
+        .collect();
+    let saliency =
+        ImageBuffer::<Luma<f32>, Vec<f32>>::from_raw(SALIENCY_SIZE, SALIENCY_SIZE, saliency)
+            .unwrap();
+    let saliency = image::imageops::blur(&saliency, 2.5);
+    let max_saliency = saliency
+        .pixels()
+        .map(|p| p.0[0])
+        .fold(f32::MIN_POSITIVE, f32::max);
+    let saliency = image::imageops::resize(
+        &saliency,
+        image.width(),
+        image.height(),
+        FilterType::Triangle,
+    );
+    saliency
+        .pixels()
+        .map(|p| MIN_WEIGHT + (1.0 - MIN_WEIGHT) * (p.0[0] / max_saliency).clamp(0.0, 1.0))
+        .collect()
+}
+
+/// In place 2D discrete Fourier transform of a n x n matrix, n being a power of 2
+/// The rows, then the columns are transformed.
+fn dft_2d(matrix: &mut [Complex], n: usize, inverse: bool) {
+    let mut line = vec![(0.0, 0.0); n];
+    for row in 0..n {
+        line.copy_from_slice(&matrix[row * n..(row + 1) * n]);
+        fft(&mut line, inverse);
+        matrix[row * n..(row + 1) * n].copy_from_slice(&line);
+    }
+    for col in 0..n {
+        for (i, value) in line.iter_mut().enumerate() {
+            *value = matrix[i * n + col];
+        }
+        fft(&mut line, inverse);
+        for (i, value) in line.iter().enumerate() {
+            matrix[i * n + col] = *value;
+        }
+    }
+}
+
+/// In place iterative radix-2 fast Fourier transform
+fn fft(line: &mut [Complex], inverse: bool) {
+    let n = line.len();
+    debug_assert!(n.is_power_of_two());
+    // Bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            line.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (even, odd) = (line[start + k], line[start + k + len / 2]);
+                let twiddled = (odd.0 * cos - odd.1 * sin, odd.0 * sin + odd.1 * cos);
+                line[start + k] = (even.0 + twiddled.0, even.1 + twiddled.1);
+                line[start + k + len / 2] = (even.0 - twiddled.0, even.1 - twiddled.1);
+            }
+        }
+        len <<= 1;
+    }
+    if inverse {
+        let scale = 1.0 / n as f32;
+        for value in line.iter_mut() {
+            *value = (value.0 * scale, value.1 * scale);
+        }
+    }
+}